@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Why a crawl stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    QueueExhausted,
+    MaxPages,
+    MaxTime,
+    MaxBytes,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::QueueExhausted => "queue_exhausted",
+            StopReason::MaxPages => "max_pages",
+            StopReason::MaxTime => "max_time",
+            StopReason::MaxBytes => "max_bytes",
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Crawl budget limits (0 disables a limit)
+#[derive(Debug, Clone, Default)]
+pub struct BudgetLimits {
+    pub max_pages: u64,
+    pub max_time: Duration,
+    pub max_bytes: u64,
+    pub max_pages_per_host: u64,
+    /// Per-host overrides of `max_pages_per_host`
+    pub host_max_pages: HashMap<String, u64>,
+}
+
+/// Settings for a single host (config file `[hosts."example.com"]`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostConfig {
+    /// Maximum pages crawled on this host, overriding `max_pages_per_host` (0 = unlimited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pages: Option<u64>,
+}
+
+impl BudgetLimits {
+    /// Add the page limits of per-host settings
+    pub fn with_hosts(mut self, hosts: &BTreeMap<String, HostConfig>) -> Self {
+        for (host, config) in hosts {
+            if let Some(max_pages) = config.max_pages {
+                self.host_max_pages.insert(host.to_lowercase(), max_pages);
+            }
+        }
+        self
+    }
+
+    fn host_limit(&self, host: &str) -> u64 {
+        self.host_max_pages.get(host).copied().unwrap_or(self.max_pages_per_host)
+    }
+}
+
+/// Budget usage carried over from an interrupted or stopped crawl
+#[derive(Debug, Clone, Default)]
+pub struct BudgetUsage {
+    pub pages: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub host_pages: HashMap<String, u64>,
+}
+
+/// Tracks crawl budget consumption across workers
+pub struct Budget {
+    limits: BudgetLimits,
+    started: Instant,
+    elapsed_before: Duration,
+    pages: AtomicU64,
+    bytes: AtomicU64,
+    host_pages: Mutex<HashMap<String, u64>>,
+    stop_reason: Mutex<Option<StopReason>>,
+}
+
+impl Budget {
+    /// Create a budget, continuing from previous usage when resuming
+    pub fn new(limits: BudgetLimits, usage: BudgetUsage) -> Self {
+        Budget {
+            limits,
+            started: Instant::now(),
+            elapsed_before: usage.elapsed,
+            pages: AtomicU64::new(usage.pages),
+            bytes: AtomicU64::new(usage.bytes),
+            host_pages: Mutex::new(usage.host_pages),
+            stop_reason: Mutex::new(None),
+        }
+    }
+
+    /// Check the global limits, returns the reason if the crawl must stop
+    pub fn exhausted(&self) -> Option<StopReason> {
+        if let Some(reason) = *self.stop_reason.lock().unwrap() {
+            return Some(reason);
+        }
+
+        let limits = &self.limits;
+        let reason = if limits.max_pages > 0 && self.pages() >= limits.max_pages {
+            Some(StopReason::MaxPages)
+        } else if limits.max_bytes > 0 && self.bytes() >= limits.max_bytes {
+            Some(StopReason::MaxBytes)
+        } else if !limits.max_time.is_zero() && self.elapsed() >= limits.max_time {
+            Some(StopReason::MaxTime)
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            self.stop_reason.lock().unwrap().get_or_insert(reason);
+        }
+        reason
+    }
+
+    /// Reserve a page for a host, returns false if the crawl or the host is out of budget
+    pub fn start_page(&self, host: &str) -> bool {
+        let mut host_pages = self.host_pages.lock().unwrap();
+        let count = host_pages.entry(host.to_string()).or_insert(0);
+        let host_limit = self.limits.host_limit(host);
+        if host_limit > 0 && *count >= host_limit {
+            return false;
+        }
+
+        // Reserve atomically so concurrent workers can't overshoot max_pages
+        let reserved = self.pages.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pages| {
+            if self.limits.max_pages > 0 && pages >= self.limits.max_pages {
+                None
+            } else {
+                Some(pages + 1)
+            }
+        });
+        if reserved.is_err() {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+
+    /// Count downloaded bytes
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.pages.load(Ordering::SeqCst)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::SeqCst)
+    }
+
+    /// Wall-clock time spent crawling, including previous sessions
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_before + self.started.elapsed()
+    }
+
+    /// Reason the crawl stopped (queue exhausted unless a limit was hit)
+    pub fn stop_reason(&self) -> StopReason {
+        self.stop_reason.lock().unwrap().unwrap_or(StopReason::QueueExhausted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_pages() {
+        let budget = Budget::new(
+            BudgetLimits { max_pages: 2, ..Default::default() },
+            BudgetUsage::default(),
+        );
+
+        assert!(budget.exhausted().is_none());
+        assert!(budget.start_page("a.com"));
+        assert!(budget.start_page("b.com"));
+        assert!(!budget.start_page("c.com"));
+        assert_eq!(budget.exhausted(), Some(StopReason::MaxPages));
+        assert_eq!(budget.stop_reason(), StopReason::MaxPages);
+    }
+
+    #[test]
+    fn test_max_pages_per_host() {
+        let budget = Budget::new(
+            BudgetLimits { max_pages_per_host: 1, ..Default::default() },
+            BudgetUsage::default(),
+        );
+
+        assert!(budget.start_page("a.com"));
+        assert!(!budget.start_page("a.com"));
+        assert!(budget.start_page("b.com"));
+        assert_eq!(budget.pages(), 2);
+        assert!(budget.exhausted().is_none());
+    }
+
+    #[test]
+    fn test_host_overrides() {
+        let hosts = BTreeMap::from([
+            ("a.com".to_string(), HostConfig { max_pages: Some(2) }),
+            ("b.com".to_string(), HostConfig { max_pages: Some(0) }),
+            ("c.com".to_string(), HostConfig::default()),
+        ]);
+        let budget = Budget::new(
+            BudgetLimits { max_pages_per_host: 1, ..Default::default() }.with_hosts(&hosts),
+            BudgetUsage::default(),
+        );
+
+        assert!(budget.start_page("a.com"));
+        assert!(budget.start_page("a.com"));
+        assert!(!budget.start_page("a.com"));
+        for _ in 0..3 {
+            assert!(budget.start_page("b.com"));
+        }
+        assert!(budget.start_page("c.com"));
+        assert!(!budget.start_page("c.com"));
+    }
+
+    #[test]
+    fn test_resume_with_raised_budget() {
+        let usage = BudgetUsage {
+            pages: 10,
+            bytes: 500,
+            elapsed: Duration::from_secs(5),
+            host_pages: HashMap::from([("a.com".to_string(), 10)]),
+        };
+
+        let budget = Budget::new(
+            BudgetLimits { max_pages: 10, ..Default::default() },
+            usage.clone(),
+        );
+        assert_eq!(budget.exhausted(), Some(StopReason::MaxPages));
+
+        let budget = Budget::new(
+            BudgetLimits { max_pages: 20, max_bytes: 1000, ..Default::default() },
+            usage,
+        );
+        assert!(budget.exhausted().is_none());
+        assert!(budget.start_page("a.com"));
+        budget.add_bytes(600);
+        assert_eq!(budget.exhausted(), Some(StopReason::MaxBytes));
+        assert!(budget.elapsed() >= Duration::from_secs(5));
+    }
+}
@@ -1,4 +1,11 @@
-use clap::Parser;
+use crate::logging::LogFormat;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use couscous_crawler::export::{ExportFormat, ExportKind};
+use couscous_crawler::maintenance::QueueStatus;
+use couscous_crawler::report::ReportFormat;
+use couscous_crawler::storage::StorageKind;
+use couscous_crawler::CrawlOptions;
+use serde::{Deserialize, Serialize};
 
 /// Couscous Crawler - A fast async web crawler that extracts emails
 #[derive(Parser, Debug, Clone)]
@@ -6,64 +13,270 @@ use clap::Parser;
 #[command(author = "Arthur")]
 #[command(version = "0.1.0")]
 #[command(about = "Crawl websites and extract emails to SQLite", long_about = None)]
-pub struct Args {
-    /// Starting URL to crawl
-    #[arg(required = true)]
-    pub url: String,
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
 
-    /// Maximum crawl depth (0 = unlimited)
-    #[arg(short, long, default_value_t = 0)]
-    pub depth: u32,
+    /// SQLite database path
+    #[arg(long, global = true, default_value = "emails.db")]
+    pub db: String,
 
-    /// Stay on the same domain only
-    #[arg(short, long, default_value_t = false)]
-    pub stay_on_domain: bool,
+    /// Verbose output (debug logs)
+    #[arg(short, long, global = true, default_value_t = false)]
+    pub verbose: bool,
 
-    /// Number of async workers
-    #[arg(short, long, default_value_t = 10)]
-    pub workers: usize,
+    /// Log format on stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
 
-    /// SQLite database path
-    #[arg(long, default_value = "emails.db")]
-    pub db: String,
+    /// Log filter, e.g. `info,couscous_crawler::crawler=debug` (overrides RUST_LOG and --verbose)
+    #[arg(long, global = true)]
+    pub log_filter: Option<String>,
 
-    /// HTTP request timeout in milliseconds
-    #[arg(short = 't', long, default_value_t = 30000)]
-    pub timeout: u64,
+    /// TOML configuration file (defaults to couscous.toml if it exists)
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Profile of the configuration file to apply over its base settings
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+}
+
+/// Crawl settings, shared by `crawl` and `resume`: the crawler's options and
+/// what only the command line decides (storage, outputs, resume target)
+///
+/// Every field except the starting URL and resume target can also be set in
+/// the configuration file, under its snake_case name.
+#[derive(clap::Args, Debug, Clone, Serialize, Deserialize)]
+pub struct Args {
+    /// Starting URL (from the command line, or the run's seed when resuming)
+    #[arg(skip)]
+    pub url: Option<String>,
+
+    /// Database path (global `--db`)
+    #[arg(skip)]
+    pub db: String,
 
-    /// Resume from existing database
-    #[arg(short, long, default_value_t = false)]
+    /// Continue a previous run instead of starting one
+    #[arg(skip)]
     pub resume: bool,
 
-    /// Verbose output
-    #[arg(short, long, default_value_t = false)]
+    /// Run to resume, the latest if unset
+    #[arg(skip)]
+    pub resume_run: Option<i64>,
+
+    /// Queue again the URLs that failed with a retryable error when resuming
+    #[arg(skip)]
+    pub retry_errors: bool,
+
+    /// Verbose output (global `--verbose`)
+    #[arg(skip)]
     pub verbose: bool,
 
-    /// Disable SSL certificate verification
-    #[arg(short = 'k', long, default_value_t = false)]
-    pub insecure: bool,
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub crawl: CrawlOptions,
+
+    /// Write the end-of-crawl statistics to this JSON file
+    #[arg(long)]
+    pub summary_json: Option<String>,
 
-    /// Enable image face detection
+    /// Write each finding to stdout as a JSON line while crawling, the report goes to stderr
     #[arg(long, default_value_t = false)]
-    pub extract_images: bool,
+    pub ndjson: bool,
+
+    /// POST findings in batches to this URL while crawling, as JSON arrays
+    #[arg(long)]
+    pub webhook: Option<String>,
+
+    /// Findings per webhook request
+    #[arg(long, default_value_t = 100)]
+    pub webhook_batch_size: usize,
+
+    /// Retries of a failed webhook request, with exponential backoff
+    #[arg(long, default_value_t = 3)]
+    pub webhook_retries: u32,
+
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9898
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+
+    /// Where the queue and findings are kept
+    #[arg(long, value_enum, default_value_t = StorageKind::Sqlite)]
+    pub storage: StorageKind,
+
+    /// Findings file for `--storage jsonl`
+    #[arg(long, default_value = "findings.jsonl")]
+    pub jsonl: String,
+}
+
+/// Subcommands, all sharing the global options
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Start a new crawl from a URL
+    Crawl {
+        /// Starting URL to crawl
+        url: String,
 
-    /// Path to YOLO face detection model (.pt file)
-    #[arg(long, default_value = "face-detection/yolov12l-face.pt")]
-    pub yolo_model: String,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Continue an interrupted crawl from its queue
+    Resume {
+        /// Run to resume (defaults to the latest)
+        #[arg(long)]
+        run: Option<i64>,
 
-    /// Minimum image width for face detection
-    #[arg(long, default_value_t = 128)]
-    pub min_image_width: u32,
+        /// Starting URL, only needed for runs recorded before seeds were kept
+        #[arg(long)]
+        url: Option<String>,
 
-    /// Minimum image height for face detection
-    #[arg(long, default_value_t = 128)]
-    pub min_image_height: u32,
+        /// Also queue again the URLs that failed with a retryable error (DNS, timeout, 5xx, ...)
+        #[arg(long)]
+        retry_errors: bool,
+
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Write findings or pages as CSV, JSON or NDJSON
+    Export {
+        /// What to export
+        #[arg(value_enum)]
+        kind: ExportKind,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Output file (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Columns to write, in order (defaults to all)
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+
+        /// Only this run
+        #[arg(long)]
+        run: Option<i64>,
+
+        /// Only runs started from this seed URL
+        #[arg(long)]
+        seed: Option<String>,
+
+        /// Only pages on this host or its subdomains
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Only emails at this domain
+        #[arg(long)]
+        email_domain: Option<String>,
+
+        /// Only seen on or after this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only seen on or before this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Only emails and phones with at least this confidence (0.5 to 1)
+        #[arg(long)]
+        min_confidence: Option<f64>,
+    },
+    /// Show per-host, status code, email domain and throughput breakdowns
+    Report {
+        /// Run to report on (defaults to all runs)
+        #[arg(long)]
+        run: Option<i64>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+
+        /// Rows kept per ranking (hosts, email domains, pages)
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Count a run's queue by status and list queued URLs
+    Queue {
+        /// Run to inspect (defaults to the latest)
+        #[arg(long)]
+        run: Option<i64>,
+
+        /// Status of the URLs to list
+        #[arg(long, value_enum, default_value_t = QueueStatus::Pending)]
+        status: QueueStatus,
+
+        /// Number of URLs to list
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Delete runs with their queue, pages and findings
+    Purge {
+        /// Run to delete (repeatable)
+        #[arg(long)]
+        run: Vec<i64>,
+
+        /// Delete all but the latest N runs
+        #[arg(long)]
+        keep_last: Option<usize>,
+
+        /// Reclaim the freed space afterwards
+        #[arg(long, default_value_t = false)]
+        vacuum: bool,
+
+        /// Only list the runs that would be deleted
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Upgrade the database schema to the latest version
+    Migrate {
+        /// Only list pending migrations
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Run the current extractors over stored pages and update findings
+    Reextract {
+        /// Run to re-extract (defaults to the latest)
+        #[arg(long)]
+        run: Option<i64>,
+
+        /// Read pages from these WARC files or directories instead of the page store
+        #[arg(long)]
+        warc: Vec<String>,
+
+        /// Only report what would be added or removed
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+/// Configuration subcommands
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    /// Print the crawl settings after merging the file, profile and command line
+    Show {
+        #[command(flatten)]
+        args: Args,
+    },
+}
 
-    /// Output directory for images with faces
-    #[arg(long, default_value = "faces")]
-    pub faces_dir: String,
+impl Args {
+    /// Starting URL (set from the command line or the resumed run's seeds)
+    pub fn start_url(&self) -> &str {
+        self.url.as_deref().unwrap_or_default()
+    }
 }
 
-pub fn parse_args() -> Args {
-    Args::parse()
+/// Parse the command line, keeping the matches to tell options given on it from defaults
+pub fn parse_args() -> (Cli, ArgMatches) {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    (cli, matches)
 }
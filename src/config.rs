@@ -0,0 +1,380 @@
+use crate::cli::{Args, Cli};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use toml::Table;
+use tracing::warn;
+
+/// Configuration file used when `--config` is not given, if it exists
+pub const DEFAULT_PATH: &str = "couscous.toml";
+
+/// Crawl settings that only come from the command line
+const RESERVED: &[&str] = &["url", "resume", "resume_run", "retry_errors"];
+
+/// Error loading or applying a configuration file
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: String, error: std::io::Error },
+    Parse { path: String, error: toml::de::Error },
+    /// `--profile` was given but there is no configuration file
+    NoFile { profile: String },
+    UnknownProfile { profile: String, path: String },
+    UnknownKey(String),
+    /// A setting that can't be set in the file
+    Reserved(String),
+    Invalid { key: String, error: String },
+    Json(serde_json::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "can't read configuration file {}: {}", path, error),
+            ConfigError::Parse { path, error } => write!(f, "invalid configuration file {}: {}", path, error),
+            ConfigError::NoFile { profile } => write!(
+                f,
+                "profile {} needs a configuration file, pass it with --config or create {}",
+                profile, DEFAULT_PATH
+            ),
+            ConfigError::UnknownProfile { profile, path } => write!(f, "no profile {} in {}", profile, path),
+            ConfigError::UnknownKey(key) => write!(f, "unknown configuration setting {}", key),
+            ConfigError::Reserved(key) => write!(f, "{} can't be set in the configuration file", key),
+            ConfigError::Invalid { key, error } => write!(f, "invalid configuration value for {}: {}", key, error),
+            ConfigError::Json(e) => write!(f, "{}", e),
+            ConfigError::Serialize(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(e: toml::ser::Error) -> Self {
+        ConfigError::Serialize(e)
+    }
+}
+
+/// Settings of a configuration file, with the selected profile merged over its base
+///
+/// Options given on the command line win over the profile, the profile over
+/// the file's base settings, and those over the built-in defaults.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// File the settings were read from
+    pub path: Option<String>,
+    pub profile: Option<String>,
+    settings: Table,
+}
+
+impl Config {
+    /// Load `path`, or `couscous.toml` if it exists, and apply `profile`
+    pub fn load(path: Option<&str>, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => path.to_string(),
+            None if Path::new(DEFAULT_PATH).exists() => DEFAULT_PATH.to_string(),
+            None => {
+                return match profile {
+                    Some(profile) => Err(ConfigError::NoFile { profile: profile.to_string() }),
+                    None => Ok(Config::default()),
+                };
+            }
+        };
+        let text = std::fs::read_to_string(&path).map_err(|error| ConfigError::Io { path: path.clone(), error })?;
+        Config::parse(&path, &text, profile)
+    }
+
+    /// Parse a configuration file and merge `profile` over its base settings
+    pub fn parse(path: &str, text: &str, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let mut settings: Table =
+            toml::from_str(text).map_err(|error| ConfigError::Parse { path: path.to_string(), error })?;
+
+        let profiles = match settings.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => {
+                return Err(ConfigError::Invalid {
+                    key: "profiles".to_string(),
+                    error: "expected a table of profiles".to_string(),
+                })
+            }
+            None => Table::new(),
+        };
+        let mut settings = normalize_keys(settings, false);
+
+        if let Some(profile) = profile {
+            match profiles.get(profile) {
+                Some(toml::Value::Table(overrides)) => merge(&mut settings, normalize_keys(overrides.clone(), false)),
+                Some(_) => {
+                    return Err(ConfigError::Invalid {
+                        key: format!("profiles.{}", profile),
+                        error: "expected a table".to_string(),
+                    })
+                }
+                None => {
+                    return Err(ConfigError::UnknownProfile { profile: profile.to_string(), path: path.to_string() })
+                }
+            }
+        }
+
+        Ok(Config { path: Some(path.to_string()), profile: profile.map(str::to_string), settings })
+    }
+
+    /// Set `--db` and `--verbose` from the file unless given on the command line
+    pub fn apply_globals(&self, cli: &mut Cli, matches: &ArgMatches) -> Result<(), ConfigError> {
+        if let Some(value) = self.settings.get("db") {
+            if !on_command_line(matches, "db") {
+                cli.db = value.as_str().ok_or_else(|| invalid("db", "expected a string"))?.to_string();
+            }
+        }
+        if let Some(value) = self.settings.get("verbose") {
+            if !on_command_line(matches, "verbose") {
+                cli.verbose = value.as_bool().ok_or_else(|| invalid("verbose", "expected a boolean"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set crawl settings from the file unless given on the command line
+    pub fn apply(&self, args: &mut Args, matches: &ArgMatches) -> Result<(), ConfigError> {
+        let Value::Object(mut merged) = serde_json::to_value(&*args)? else {
+            unreachable!("crawl settings serialize to an object");
+        };
+
+        for (key, value) in &self.settings {
+            if RESERVED.contains(&key.as_str()) {
+                return Err(ConfigError::Reserved(key.clone()));
+            }
+            if !merged.contains_key(key) {
+                return Err(ConfigError::UnknownKey(key.clone()));
+            }
+            if on_command_line(matches, key) {
+                continue;
+            }
+
+            merged.insert(key.clone(), serde_json::to_value(value)?);
+            // Check each value on its own so errors name the setting
+            if let Err(e) = serde_json::from_value::<Args>(Value::Object(merged.clone())) {
+                return Err(invalid(key, &e.to_string()));
+            }
+        }
+
+        *args = serde_json::from_value(Value::Object(merged))?;
+        Ok(())
+    }
+
+    /// Set the crawl options a resumed run was started with (`recorded`, JSON),
+    /// unless given on the command line or in the file
+    ///
+    /// Recorded options this version no longer accepts are skipped with a warning.
+    pub fn apply_recorded(&self, args: &mut Args, recorded: &str, matches: &ArgMatches) -> Result<(), ConfigError> {
+        let Value::Object(recorded) = serde_json::from_str(recorded)? else {
+            return Ok(());
+        };
+        let Value::Object(options) = serde_json::to_value(&args.crawl)? else {
+            unreachable!("crawl options serialize to an object");
+        };
+        let Value::Object(mut merged) = serde_json::to_value(&*args)? else {
+            unreachable!("crawl settings serialize to an object");
+        };
+
+        // Runs started before the options were split from the rest recorded every setting
+        for (key, value) in recorded {
+            if !options.contains_key(&key) || self.settings.contains_key(&key) || on_command_line(matches, &key) {
+                continue;
+            }
+            let previous = merged.insert(key.clone(), value);
+            if let Err(e) = serde_json::from_value::<Args>(Value::Object(merged.clone())) {
+                warn!(setting = %key, error = %e, "Ignoring recorded option of the resumed run");
+                if let Some(previous) = previous {
+                    merged.insert(key, previous);
+                }
+            }
+        }
+
+        *args = serde_json::from_value(Value::Object(merged))?;
+        Ok(())
+    }
+}
+
+/// Crawl settings as a configuration file, without the command-line only ones
+pub fn to_toml(args: &Args) -> Result<String, ConfigError> {
+    let Value::Object(mut settings) = serde_json::to_value(args)? else {
+        unreachable!("crawl settings serialize to an object");
+    };
+    for key in RESERVED {
+        settings.remove(*key);
+    }
+    // TOML has no null, unset options are left out
+    settings.retain(|_, value| !value.is_null());
+    Ok(toml::to_string(&settings)?)
+}
+
+fn invalid(key: &str, error: &str) -> ConfigError {
+    ConfigError::Invalid { key: key.to_string(), error: error.to_string() }
+}
+
+/// Whether an option was given on the command line, in any subcommand
+fn on_command_line(matches: &ArgMatches, id: &str) -> bool {
+    // Unknown ids would panic in value_source
+    let given = matches.try_get_raw(id).is_ok() && matches.value_source(id) == Some(ValueSource::CommandLine);
+    given || matches.subcommand().is_some_and(|(_, sub)| on_command_line(sub, id))
+}
+
+/// Accept kebab-case keys like on the command line, host names are kept as is
+fn normalize_keys(table: Table, host_names: bool) -> Table {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let key = if host_names { key } else { key.replace('-', "_") };
+            let value = match value {
+                toml::Value::Table(table) => toml::Value::Table(normalize_keys(table, !host_names && key == "hosts")),
+                value => value,
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+/// Merge `overrides` into `settings`, tables are merged key by key
+fn merge(settings: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match value {
+            toml::Value::Table(table) if settings.get(&key).is_some_and(toml::Value::is_table) => {
+                if let Some(toml::Value::Table(base)) = settings.get_mut(&key) {
+                    merge(base, table);
+                }
+            }
+            value => {
+                settings.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+    use couscous_crawler::strategy::CrawlStrategy;
+    use clap::{CommandFactory, FromArgMatches};
+
+    const CONFIG: &str = r#"
+        db = "leads.db"
+        workers = 4
+        max-pages = 100
+        priority_keywords = ["contact"]
+
+        [scope]
+        exclude = ["/tag/"]
+
+        [hosts."my-host.fr"]
+        max-pages = 5
+
+        [profiles.deep]
+        depth = 0
+        max_pages = 1000
+        strategy = "best-first"
+        scope = { include = ["^https://a\\.fr/"] }
+    "#;
+
+    fn crawl(command_line: &[&str], profile: Option<&str>) -> Result<(Cli, Args), ConfigError> {
+        let matches = Cli::command().get_matches_from(command_line);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let config = Config::parse("test.toml", CONFIG, profile)?;
+        config.apply_globals(&mut cli, &matches)?;
+        let Command::Crawl { mut args, .. } = cli.command.clone() else {
+            panic!("not a crawl");
+        };
+        config.apply(&mut args, &matches)?;
+        Ok((cli, args))
+    }
+
+    #[test]
+    fn test_precedence() {
+        // File over defaults
+        let (cli, args) = crawl(&["couscous", "crawl", "https://a.fr/"], None).unwrap();
+        assert_eq!(cli.db, "leads.db");
+        assert_eq!((args.crawl.workers, args.crawl.max_pages, args.crawl.depth), (4, 100, 0));
+        assert_eq!(args.crawl.timeout, 30000);
+        assert_eq!(args.crawl.priority_keywords, vec!["contact"]);
+        assert_eq!(args.crawl.scope.exclude, vec!["/tag/"]);
+        assert_eq!(args.crawl.hosts["my-host.fr"].max_pages, Some(5));
+
+        // Profile over file, tables merged key by key
+        let (_, args) = crawl(&["couscous", "crawl", "https://a.fr/"], Some("deep")).unwrap();
+        assert_eq!((args.crawl.workers, args.crawl.max_pages), (4, 1000));
+        assert_eq!(args.crawl.strategy, CrawlStrategy::BestFirst);
+        assert_eq!(args.crawl.scope.include.len(), 1);
+        assert_eq!(args.crawl.scope.exclude, vec!["/tag/"]);
+
+        // Command line over profile, even when given the default value
+        let command_line = ["couscous", "crawl", "https://a.fr/", "--max-pages", "0", "--db", "cli.db", "-w", "10"];
+        let (cli, args) = crawl(&command_line, Some("deep")).unwrap();
+        assert_eq!(cli.db, "cli.db");
+        assert_eq!((args.crawl.workers, args.crawl.max_pages), (10, 0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let command_line = ["couscous", "crawl", "https://a.fr/"];
+        assert!(matches!(crawl(&command_line, Some("wide")), Err(ConfigError::UnknownProfile { .. })));
+
+        let matches = Cli::command().get_matches_from(command_line);
+        let Command::Crawl { mut args, .. } = Cli::from_arg_matches(&matches).unwrap().command else {
+            panic!("not a crawl");
+        };
+        for (text, expected) in [
+            ("max_page = 1", "unknown configuration setting max_page"),
+            ("url = \"https://b.fr/\"", "url can't be set in the configuration file"),
+            ("workers = \"many\"", "invalid configuration value for workers"),
+            ("strategy = \"random\"", "invalid configuration value for strategy"),
+        ] {
+            let error = Config::parse("test.toml", text, None).unwrap().apply(&mut args, &matches).unwrap_err();
+            assert!(error.to_string().starts_with(expected), "{}", error);
+        }
+        assert!(matches!(Config::parse("test.toml", "workers =", None), Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_recorded_options() {
+        let recorded = r#"{"url": "https://a.fr/", "depth": 2, "stay_on_domain": true, "workers": 8,
+                          "max_pages": 50, "strategy": "dfs", "timeout": "slow"}"#;
+        let matches = Cli::command().get_matches_from(["couscous", "resume", "--run", "3", "--depth", "5"]);
+        let Command::Resume { mut args, .. } = Cli::from_arg_matches(&matches).unwrap().command else {
+            panic!("not a resume");
+        };
+        let config = Config::parse("test.toml", CONFIG, None).unwrap();
+        config.apply(&mut args, &matches).unwrap();
+        config.apply_recorded(&mut args, recorded, &matches).unwrap();
+
+        // Command line over file over recorded, invalid recorded values are skipped
+        assert_eq!(args.crawl.depth, 5);
+        assert_eq!((args.crawl.workers, args.crawl.max_pages), (4, 100));
+        assert!(args.crawl.stay_on_domain);
+        assert_eq!(args.crawl.strategy, CrawlStrategy::Dfs);
+        assert_eq!(args.crawl.timeout, 30000);
+        assert!(args.url.is_none());
+    }
+
+    #[test]
+    fn test_show_round_trip() {
+        let (_, args) = crawl(&["couscous", "crawl", "https://a.fr/"], Some("deep")).unwrap();
+        let text = to_toml(&args).unwrap();
+        assert!(!text.contains("url ="));
+
+        let matches = Cli::command().get_matches_from(["couscous", "crawl", "https://b.fr/"]);
+        let Command::Crawl { args: mut shown, .. } = Cli::from_arg_matches(&matches).unwrap().command else {
+            panic!("not a crawl");
+        };
+        Config::parse("shown.toml", &text, None).unwrap().apply(&mut shown, &matches).unwrap();
+        assert_eq!(to_toml(&shown).unwrap(), text);
+    }
+}
@@ -1,13 +1,35 @@
-use crate::cli::Args;
-use crate::database::Database;
-use crate::extractor::{extract_emails, extract_links, extract_phones, is_same_domain};
+use crate::budget::{Budget, BudgetLimits, BudgetUsage, StopReason};
+use crate::error::CrawlError;
+use crate::events::{CrawlEvent, EventStream, Events};
+use crate::extractor::{extract_links, is_same_domain, Link};
+use crate::fingerprint::{Fingerprint, FingerprintIndex};
 use crate::image_processor::ImageProcessor;
-use colored::*;
+use crate::metrics;
+use crate::options::CrawlOptions;
+use crate::progress::ProgressDisplay;
+use crate::robots::RobotsDirectives;
+use crate::scope::Scope;
+use crate::seen::SeenFilter;
+use crate::stats::CrawlStats;
+use crate::storage::{self, MemoryStorage, RunRecord, Storage};
+use crate::strategy::{score_url, CrawlStrategy};
+use crate::trap::{TrapDetector, TrapLimits};
+use crate::warc::{HttpExchange, WarcWriter};
 use rand::Rng;
+use reqwest::header::{HeaderMap, USER_AGENT};
+use reqwest::StatusCode;
 use reqwest::Client;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use url::Url;
 
+/// Period at which the run's elapsed time is saved, for resuming an interrupted crawl
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 // Common user agents for stealth
 const USER_AGENTS: &[&str] = &[
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
@@ -18,76 +40,429 @@ const USER_AGENTS: &[&str] = &[
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0",
 ];
 
-/// Crawler state
+/// Crawler state, created with a [`CrawlerBuilder`]
 pub struct Crawler {
-    db: Arc<Database>,
-    args: Args,
+    ctx: Arc<CrawlContext>,
+    // Show the crawl status while the workers run
+    progress: bool,
+}
+
+/// State shared by all workers
+struct CrawlContext {
+    db: Arc<dyn Storage>,
+    options: CrawlOptions,
+    start_url: String,
     base_domain: String,
+    // Include and exclude patterns of the configuration file
+    scope: Scope,
+    traps: Arc<TrapDetector>,
+    fingerprints: FingerprintIndex,
+    // Queued and visited URLs, answers most lookups without the storage
+    seen: SeenFilter,
+    budget: Budget,
+    stats: Arc<CrawlStats>,
+    warc: Option<Arc<WarcWriter>>,
+    events: Events,
 }
 
-impl Crawler {
-    /// Create a new crawler instance
-    pub fn new(args: Args, db: Arc<Database>) -> Result<Self, Box<dyn std::error::Error>> {
-        let start_url = Url::parse(&args.url)?;
-        let base_domain = start_url
-            .host_str()
-            .ok_or("Invalid URL: no host")?
-            .to_string();
-
-        Ok(Crawler {
-            db,
-            args,
-            base_domain,
-        })
+/// Outcome of processing a queued URL
+enum PageOutcome {
+    Done,
+    /// The URL's host is out of budget, keep it for a later resume
+    Deferred,
+    /// The crawl budget is spent, the worker must stop
+    BudgetExhausted,
+}
+
+/// How a crawl starts
+enum Start {
+    Seed(String),
+    Resume { run: Option<i64>, url: Option<String>, retry_errors: bool },
+}
+
+/// Configures and creates a [`Crawler`]
+///
+/// Options left unset keep their command-line defaults. Without a storage the
+/// queue and findings are kept in memory.
+pub struct CrawlerBuilder {
+    start: Start,
+    options: CrawlOptions,
+    storage: Option<Arc<dyn Storage>>,
+    progress: bool,
+}
+
+impl CrawlerBuilder {
+    /// Start a new run from a seed URL
+    pub fn new(seed: &str) -> Self {
+        CrawlerBuilder::starting(Start::Seed(seed.to_string()))
+    }
+
+    /// Continue a previous run from its queue, see [`run_to_resume`] for the run picked
+    ///
+    /// The storage must keep runs across processes, like [`crate::Database`].
+    pub fn resume(run: Option<i64>) -> Self {
+        CrawlerBuilder::starting(Start::Resume { run, url: None, retry_errors: false })
+    }
+
+    fn starting(start: Start) -> Self {
+        CrawlerBuilder { start, options: CrawlOptions::default(), storage: None, progress: false }
     }
 
-    /// Initialize the crawl (queue start URL or resume)
-    pub fn init(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.args.resume {
-            // Reset any URLs that were processing when interrupted
-            let reset = self.db.reset_processing()?;
-            if reset > 0 {
-                println!("Resumed {} interrupted URLs", reset);
+    /// Seed URL of a resumed run, only needed for runs recorded before seeds were kept
+    pub fn seed(mut self, url: &str) -> Self {
+        match &mut self.start {
+            Start::Seed(seed) => *seed = url.to_string(),
+            Start::Resume { url: seed, .. } => *seed = Some(url.to_string()),
+        }
+        self
+    }
+
+    /// Queue again the URLs of a resumed run that failed with a retryable error
+    pub fn retry_errors(mut self, retry: bool) -> Self {
+        if let Start::Resume { retry_errors, .. } = &mut self.start {
+            *retry_errors = retry;
+        }
+        self
+    }
+
+    /// Replace all the options
+    pub fn options(mut self, options: CrawlOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Where the run's queue and findings are kept, in memory by default
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Show the crawl status while crawling: a live area on a terminal, periodic logs otherwise
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Maximum depth of followed links (0 = unlimited)
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.options.depth = depth;
+        self
+    }
+
+    /// Only follow links on the seed URL's domain
+    pub fn stay_on_domain(mut self, stay: bool) -> Self {
+        self.options.stay_on_domain = stay;
+        self
+    }
+
+    /// Number of pages crawled concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.options.workers = workers;
+        self
+    }
+
+    /// Order in which queued URLs are crawled
+    pub fn strategy(mut self, strategy: CrawlStrategy) -> Self {
+        self.options.strategy = strategy;
+        self
+    }
+
+    /// Timeout of each HTTP request
+    ///
+    /// Rounded up to whole milliseconds, so a sub-millisecond timeout isn't truncated to zero.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout.as_micros().div_ceil(1000) as u64;
+        self
+    }
+
+    /// Stop after crawling this many pages (0 = unlimited)
+    pub fn max_pages(mut self, pages: u64) -> Self {
+        self.options.max_pages = pages;
+        self
+    }
+
+    /// Stop after crawling for this long, across resumes (zero = unlimited)
+    ///
+    /// Rounded up to whole seconds, so a short limit never turns into no limit.
+    pub fn max_time(mut self, time: Duration) -> Self {
+        self.options.max_time = time.as_millis().div_ceil(1000) as u64;
+        self
+    }
+
+    /// Stop after downloading this many bytes of pages (0 = unlimited)
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.options.max_bytes = bytes;
+        self
+    }
+
+    /// Start or resume the run and queue its seed
+    pub fn build(self) -> Result<Crawler, Box<dyn std::error::Error>> {
+        let options = self.options;
+        let storage = self.storage.unwrap_or_else(|| Arc::new(MemoryStorage::new()));
+
+        // Resume a previous run from its first seed, or start a new one;
+        // budgets count usage from previous sessions when resuming
+        let (db, resumed, usage, start_url, base_domain) = match self.start {
+            Start::Resume { run, url, retry_errors } => {
+                let run = run_to_resume(storage.as_ref(), run)?;
+                let db = storage.for_run(run.id);
+                // Runs from before seeds were recorded need the URL given again
+                let seeds: Vec<String> = serde_json::from_str(&run.seeds)?;
+                let start_url = seeds
+                    .into_iter()
+                    .next()
+                    .or(url)
+                    .ok_or("The run has no seed URL, pass it with --url")?;
+                let base_domain = base_domain(&start_url)?;
+                let usage = load_budget_usage(db.as_ref(), &run)?;
+                (db, Some((run, retry_errors)), usage, start_url, base_domain)
             }
-            let pending = self.db.pending_count()?;
-            println!("Pending URLs in queue: {}", pending);
-        } else {
-            // Clear queue and start fresh
-            self.db.clear_queue()?;
-            self.db.queue_url(&self.args.url, 1)?;
+            Start::Seed(start_url) => {
+                let base_domain = base_domain(&start_url)?;
+                let seeds = serde_json::to_string(&[&start_url])?;
+                let db = storage.for_run(storage.begin_run(&seeds, &serde_json::to_string(&options)?)?);
+                (db, None, BudgetUsage::default(), start_url, base_domain)
+            }
+        };
+
+        let scope = Scope::new(&options.scope)?;
+        let traps = Arc::new(TrapDetector::new(TrapLimits {
+            max_url_length: options.max_url_length,
+            max_repeated_segments: options.max_repeated_segments,
+            max_query_variants: options.max_query_variants,
+            max_pages_per_pattern: options.max_pages_per_pattern,
+        }));
+        let fingerprints = FingerprintIndex::new(options.simhash_distance);
+        let seen = SeenFilter::new(options.seen_filter_size);
+        let stats = Arc::new(CrawlStats::new(options.workers));
+        let warc = match &options.warc {
+            Some(dir) => {
+                let prefix = format!("couscous-run{}", db.run_id());
+                Some(Arc::new(WarcWriter::new(dir, &prefix, options.warc_max_size.saturating_mul(1024 * 1024))?))
+            }
+            None => None,
+        };
+
+        let budget = Budget::new(
+            BudgetLimits {
+                max_pages: options.max_pages,
+                max_time: Duration::from_secs(options.max_time),
+                max_bytes: options.max_bytes,
+                max_pages_per_host: options.max_pages_per_host,
+                ..Default::default()
+            }
+            .with_hosts(&options.hosts),
+            usage,
+        );
+
+        let crawler = Crawler {
+            ctx: Arc::new(CrawlContext {
+                db,
+                options,
+                start_url,
+                base_domain,
+                scope,
+                traps,
+                fingerprints,
+                seen,
+                budget,
+                stats,
+                warc,
+                events: Events::default(),
+            }),
+            progress: self.progress,
+        };
+        match resumed {
+            Some((run, retry_errors)) => crawler.init_resumed(&run, retry_errors)?,
+            None => crawler.init_new()?,
+        }
+        Ok(crawler)
+    }
+}
+
+impl Crawler {
+    /// Continue a resumed run's queue, warming the in-memory indexes
+    fn init_resumed(&self, run: &RunRecord, retry_errors: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let db = &self.ctx.db;
+        info!(run = run.id, seeds = %run.seeds, "Resuming run");
+
+        // Reset any URLs that were processing when interrupted
+        let reset = db.reset_processing()?;
+        if reset > 0 {
+            info!(urls = reset, "Resumed interrupted URLs");
+        }
+        if retry_errors {
+            let requeued = db.requeue_failed()?;
+            info!(urls = requeued, "Requeued failed URLs");
+        }
+        let pending = db.pending_count()?;
+        info!(urls = pending, "Pending URLs in queue");
+        if let Some(reason) = &run.stop_reason {
+            info!(reason = %reason, pages = self.ctx.budget.pages(), "Previous crawl stopped");
         }
+
+        // Warm the duplicate index with pages fetched before the interruption
+        for (url, content_hash, simhash) in db.get_page_fingerprints()? {
+            self.ctx.fingerprints.insert(&url, Fingerprint { exact: content_hash, simhash });
+        }
+
+        // Warm the seen filter so known links skip the database
+        let seen = &self.ctx.seen;
+        db.for_each_seen_url(&mut |url, visited| {
+            if visited {
+                seen.mark_visited(url);
+            } else {
+                seen.claim(url);
+            }
+        })?;
+        if !seen.is_complete() {
+            info!(urls = seen.len(), "Seen filter full, checking the rest in the database");
+        }
+        Ok(())
+    }
+
+    /// A new run starts with an empty queue
+    fn init_new(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.ctx.seen.claim(&self.ctx.start_url);
+        self.ctx.db.queue_url(&self.ctx.start_url, 1, 0)?;
         Ok(())
     }
 
     /// Run the crawler
     pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let display = self.progress.then(|| ProgressDisplay::start(self.ctx.stats.clone(), self.ctx.db.clone()));
+        let (stop, stopped) = watch::channel(false);
+        let saver = tokio::spawn(save_progress(self.ctx.clone(), stopped));
+
         // Spawn workers
         let mut handles = vec![];
         
-        for _ in 0..self.args.workers {
-            let db = self.db.clone();
-            let args = self.args.clone();
-            let base_domain = self.base_domain.clone();
+        for id in 0..self.ctx.options.workers {
+            let ctx = self.ctx.clone();
             
-            handles.push(tokio::spawn(async move {
-                worker_loop(db, args, base_domain).await;
-            }));
+            handles.push(tokio::spawn(worker_loop(ctx, id).instrument(info_span!("worker", id))));
         }
 
         // Wait for all workers
         for handle in handles {
             let _ = handle.await;
         }
+        if let Some(display) = display {
+            display.finish().await;
+        }
+        let _ = stop.send(true);
+        let _ = saver.await;
 
-        Ok(())
+        // Record the run's outcome so a resume continues where it stopped
+        let budget = &self.ctx.budget;
+        let result = self.ctx.db.finish_run(
+            budget.stop_reason().as_str(),
+            budget.elapsed().as_millis() as u64,
+            budget.pages(),
+        );
+
+        let finished = CrawlEvent::CrawlFinished {
+            run: self.run_id(),
+            stop_reason: budget.stop_reason().to_string(),
+            pages: budget.pages(),
+        };
+        self.ctx.events.emit(finished).await;
+        self.ctx.events.close();
+
+        Ok(result?)
+    }
+
+    /// Events of the crawl as they happen, buffering up to `capacity` of them
+    ///
+    /// Subscribe before [`Crawler::run`] and consume the stream in another task:
+    /// once the buffer is full the workers wait for the consumer to catch up.
+    pub fn subscribe(&self, capacity: usize) -> EventStream {
+        self.ctx.events.subscribe(capacity)
+    }
+
+    /// Seed URL of the run
+    pub fn start_url(&self) -> &str {
+        &self.ctx.start_url
+    }
+
+    /// Id of the run being crawled
+    pub fn run_id(&self) -> i64 {
+        self.ctx.db.run_id()
+    }
+
+    /// Storage handle on the run being crawled
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.ctx.db
+    }
+
+    /// Reason the crawl stopped
+    pub fn stop_reason(&self) -> StopReason {
+        self.ctx.budget.stop_reason()
+    }
+
+    /// Pages crawled, including previous sessions when resuming
+    pub fn pages_crawled(&self) -> u64 {
+        self.ctx.budget.pages()
+    }
+
+    /// Bytes downloaded, including previous sessions when resuming
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.ctx.budget.bytes()
+    }
+
+    /// Time spent crawling, including previous sessions when resuming
+    pub fn crawl_time(&self) -> Duration {
+        self.ctx.budget.elapsed()
     }
+
+    /// Counters of this session
+    pub fn stats(&self) -> &CrawlStats {
+        &self.ctx.stats
+    }
+}
+
+/// The run to resume: `run`, or else the latest one unless its queue was exhausted
+///
+/// A run that ran out of URLs has nothing left to crawl, so it is only
+/// resumed when asked for by id (to retry its errors, for instance).
+pub fn run_to_resume(storage: &dyn Storage, run: Option<i64>) -> Result<RunRecord, Box<dyn std::error::Error>> {
+    let record = storage.find_run(run)?.ok_or("No previous run to resume")?;
+    if run.is_none() && record.stop_reason.as_deref() == Some(StopReason::QueueExhausted.as_str()) {
+        return Err(format!(
+            "The latest run #{} already finished (queue exhausted), pass --run {} to resume it anyway",
+            record.id, record.id
+        )
+        .into());
+    }
+    Ok(record)
+}
+
+/// Host of the starting URL, links are compared to it with `--stay-on-domain`
+fn base_domain(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let url = Url::parse(url)?;
+    Ok(url.host_str().ok_or("Invalid URL: no host")?.to_string())
 }
 
-/// Create a stealthy HTTP client with random user agent
-fn create_stealth_client(timeout_ms: u64, insecure: bool) -> Result<Client, reqwest::Error> {
+/// Load budget usage persisted by a previous session of the run
+fn load_budget_usage(db: &dyn Storage, run: &RunRecord) -> Result<BudgetUsage, Box<dyn std::error::Error>> {
+    let mut usage = BudgetUsage::default();
+    for (host, pages, bytes) in db.get_host_usage()? {
+        usage.pages += pages;
+        usage.bytes += bytes;
+        usage.host_pages.insert(host, pages);
+    }
+    usage.elapsed = Duration::from_millis(run.elapsed_ms);
+    Ok(usage)
+}
+
+/// Pick a random browser user agent
+fn random_user_agent() -> &'static str {
     let mut rng = rand::thread_rng();
-    let user_agent = USER_AGENTS[rng.gen_range(0..USER_AGENTS.len())];
-    
+    USER_AGENTS[rng.gen_range(0..USER_AGENTS.len())]
+}
+
+/// Create a stealthy HTTP client sending `user_agent`
+fn create_stealth_client(user_agent: &str, timeout_ms: u64, insecure: bool) -> Result<Client, reqwest::Error> {
     Client::builder()
         .user_agent(user_agent)
         .timeout(std::time::Duration::from_millis(timeout_ms))
@@ -104,16 +479,38 @@ async fn stealth_delay() {
     tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
 }
 
-async fn worker_loop(
-    db: Arc<Database>,
-    args: Args,
-    base_domain: String,
-) {
+/// Save the time spent crawling periodically until `stopped`
+///
+/// Without it an interrupted crawl would resume its time budget from the
+/// previous session's end.
+async fn save_progress(ctx: Arc<CrawlContext>, mut stopped: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval_at(Instant::now() + PROGRESS_SAVE_INTERVAL, PROGRESS_SAVE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stopped.changed() => break,
+        }
+        let elapsed = ctx.budget.elapsed().as_millis() as u64;
+        let pages = ctx.budget.pages();
+        if let Err(e) = storage::call(&ctx.db, move |db| db.save_progress(elapsed, pages)).await {
+            error!(error = %e, "Can't save crawl progress");
+        }
+    }
+}
+
+async fn worker_loop(ctx: Arc<CrawlContext>, id: usize) {
+    let db = &ctx.db;
     let mut idle_count = 0;
     
     loop {
+        // Stop once a global budget is spent
+        if ctx.budget.exhausted().is_some() {
+            break;
+        }
+
         // Try to get a task from the database queue
-        let task = db.pop_url().ok().flatten();
+        let strategy = ctx.options.strategy;
+        let task = storage::call(db, move |db| db.pop_url(strategy)).await.ok().flatten();
 
         match task {
             Some((url, depth)) => {
@@ -122,8 +519,20 @@ async fn worker_loop(
                 // Stealth delay between requests
                 stealth_delay().await;
                 
-                process_url(&db, &args, &base_domain, &url, depth).await;
-                let _ = db.complete_url(&url);
+                let span = info_span!("page", url = %url, host = tracing::field::Empty, depth);
+                ctx.stats.set_current(id, Some(&url));
+                let outcome = process_url(&ctx, &url, depth).instrument(span).await;
+                ctx.stats.set_current(id, None);
+                let owned_url = url.clone();
+                let result = match outcome {
+                    PageOutcome::Done => storage::call(db, move |db| db.complete_url(&owned_url)).await,
+                    PageOutcome::Deferred => storage::call(db, move |db| db.defer_url(&owned_url)).await,
+                    // Left as processing, reset to pending on resume
+                    PageOutcome::BudgetExhausted => break,
+                };
+                if let Err(e) = result {
+                    record_error(&ctx, &url, e.into(), "Can't update queue").await;
+                }
             }
             None => {
                 // No task available, wait a bit
@@ -140,156 +549,583 @@ async fn worker_loop(
     }
 }
 
-async fn process_url(
-    db: &Arc<Database>,
-    args: &Args,
-    base_domain: &str,
-    url: &str,
-    depth: u32,
-) {
-    // Check if already visited
-    if db.is_visited(url).unwrap_or(true) {
-        return;
-    }
-    let _ = db.mark_visited(url);
+async fn process_url(ctx: &CrawlContext, url: &str, depth: u32) -> PageOutcome {
+    let db = &ctx.db;
+    let options = &ctx.options;
 
-    if args.verbose {
-        println!("{}", format!("[Crawling] {} (depth: {})", url, depth).white());
+    // Check if already visited, asking the storage only if the filter can't tell
+    let visited = match ctx.seen.is_visited(url) {
+        Some(visited) => visited,
+        None => {
+            let owned_url = url.to_string();
+            storage::call(db, move |db| db.is_visited(&owned_url)).await.unwrap_or(true)
+        }
+    };
+    if visited {
+        return PageOutcome::Done;
     }
 
     // Parse URL
     let parsed_url = match Url::parse(url) {
         Ok(u) => u,
-        Err(_) => return,
+        Err(_) => return PageOutcome::Done,
     };
+    let host = parsed_url.host_str().unwrap_or("").to_string();
+    tracing::Span::current().record("host", tracing::field::display(&host));
+
+    // Reserve a page from the crawl and host budgets
+    if !ctx.budget.start_page(&host) {
+        if ctx.budget.exhausted().is_some() {
+            return PageOutcome::BudgetExhausted;
+        }
+        debug!("Host out of budget, deferring");
+        return PageOutcome::Deferred;
+    }
+
+    ctx.stats.add_page();
+    ctx.seen.mark_visited(url);
+    let owned_url = url.to_string();
+    if let Err(e) = storage::call(db, move |db| db.mark_visited(&owned_url)).await {
+        record_error(ctx, url, e.into(), "Can't mark visited").await;
+    }
+
+    debug!("Crawling");
 
     // Create a new client for each request (with random user agent)
-    let client = match create_stealth_client(args.timeout, args.insecure) {
+    let user_agent = random_user_agent();
+    let client = match create_stealth_client(user_agent, options.timeout, options.insecure) {
         Ok(c) => c,
         Err(e) => {
-            if args.verbose {
-                eprintln!("{}", format!("[Error] {}: {}", url, e).red());
-            }
-            return;
+            record_page_failure(ctx, url, e.into(), "Can't create HTTP client").await;
+            add_host_usage(ctx, url, host, 0).await;
+            return PageOutcome::Done;
         }
     };
 
     // Fetch the page
-    let html = match fetch_page(&client, &parsed_url).await {
-        Ok(content) => content,
+    ctx.stats.fetch_started();
+    let timer = metrics::FETCH_DURATION.start_timer();
+    let page = fetch_page(&client, &parsed_url, user_agent, options.max_body_size).await;
+    timer.observe_duration();
+    ctx.stats.fetch_finished();
+    let page = match page {
+        Ok(page) => {
+            metrics::PAGES_FETCHED.with_label_values(&[metrics::status_class(page.status.as_u16())]).inc();
+            ctx.stats.add_status(page.status.as_u16());
+            let fetched = CrawlEvent::PageFetched {
+                url: url.to_string(),
+                status: page.status.as_u16(),
+                bytes: page.body.len() as u64,
+                depth,
+            };
+            ctx.events.emit(fetched).await;
+            page
+        }
         Err(e) => {
-            if args.verbose {
-                eprintln!("{}", format!("[Error] {}: {}", url, e).red());
-            }
-            return;
+            metrics::PAGES_FETCHED.with_label_values(&["error"]).inc();
+            record_page_failure(ctx, url, e, "Fetch failed").await;
+            add_host_usage(ctx, url, host, 0).await;
+            return PageOutcome::Done;
         }
     };
 
-    // Extract emails
-    let emails = extract_emails(&html);
-    let mut new_emails = 0;
-    for email in &emails {
-        match db.insert_email(email, url) {
-            Ok(true) => new_emails += 1,
-            Ok(false) => {}
-            Err(e) => {
-                if args.verbose {
-                    eprintln!("{}", format!("[DB Error] {}", e).red());
-                }
+    // Server errors and rate limiting are worth retrying, the page is still processed
+    if let Some(e) = CrawlError::from_status(page.status.as_u16()) {
+        record_error(ctx, url, e, "Server error").await;
+    }
+
+    let bytes = page.body.len() as u64;
+    ctx.budget.add_bytes(bytes);
+    metrics::BYTES_DOWNLOADED.inc_by(bytes);
+    add_host_usage(ctx, url, host, bytes).await;
+
+    // Indexing directives from the response headers and meta tags
+    let robots = if options.ignore_robots_meta {
+        RobotsDirectives::default()
+    } else {
+        page.headers
+            .get_all("x-robots-tag")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(RobotsDirectives::from_header)
+            .fold(RobotsDirectives::from_html(&page.body), RobotsDirectives::merge)
+    };
+
+    // Archive the exchange unless the page opts out
+    if let (Some(warc), Some(exchange)) = (&ctx.warc, page.exchange) {
+        if !robots.noarchive {
+            let warc = warc.clone();
+            let result = tokio::task::spawn_blocking(move || warc.write_exchange(&exchange)).await;
+            if let Ok(Err(e)) = result {
+                record_error(ctx, url, e.into(), "Can't write WARC record").await;
             }
+        } else {
+            debug!("Not archived (noarchive)");
         }
     }
+    let status = page.status.as_u16();
+    let html = page.body;
 
-    if !emails.is_empty() {
-        println!("{}", format!("Found {} emails ({} new) on {}", emails.len(), new_emails, url).green());
+    // Keep the content for re-extraction, only where findings are stored
+    if options.store_pages && !html.is_empty() && !robots.noindex && !robots.noarchive {
+        let owned_url = url.to_string();
+        let content = html.clone();
+        if let Err(e) = storage::call(db, move |db| db.store_page_content(&owned_url, &content)).await {
+            record_error(ctx, url, e.into(), "Can't store page content").await;
+        }
     }
 
-    // Extract phones
-    let phones = extract_phones(&html);
-    let mut new_phones = 0;
-    for phone in &phones {
-        match db.insert_phone(phone, url) {
-            Ok(true) => new_phones += 1,
-            Ok(false) => {}
+    if robots.noindex || robots.nofollow {
+        debug!(noindex = robots.noindex, nofollow = robots.nofollow, "Robots directives");
+    }
+
+    // Fingerprint the page and look for an already crawled copy
+    let fingerprint = Fingerprint::of_html(&html);
+    let duplicate_of = fingerprint.and_then(|fp| ctx.fingerprints.check_and_insert(url, fp));
+    let owned_url = url.to_string();
+    let original = duplicate_of.clone();
+    let result = storage::call(db, move |db| {
+        db.insert_page(
+            &owned_url,
+            Some(status),
+            fingerprint.map(|fp| (fp.exact, fp.simhash)),
+            original.as_deref(),
+        )
+    })
+    .await;
+    if let Err(e) = result {
+        record_error(ctx, url, e.into(), "Can't store page").await;
+    }
+
+    if let Some(original) = &duplicate_of {
+        debug!(original = %original, "Duplicate page");
+    }
+
+    // Findings are not stored for pages that opt out of indexing
+    if !robots.noindex {
+        store_findings(ctx, &client, url, &parsed_url, &html, duplicate_of.is_none()).await;
+    }
+
+    // Check depth limit, duplicates are not expanded
+    let should_follow_links = (options.depth == 0 || depth < options.depth)
+        && duplicate_of.is_none()
+        && !robots.nofollow;
+    
+    if should_follow_links {
+        queue_links(ctx, &html, &parsed_url, depth).await;
+    }
+
+    PageOutcome::Done
+}
+
+/// Extract links from a page and queue the new ones, in one batch
+async fn queue_links(ctx: &CrawlContext, html: &str, parsed_url: &Url, depth: u32) {
+    let db = &ctx.db;
+    let options = &ctx.options;
+
+    let links: Vec<Link> = extract_links(html, parsed_url, !options.ignore_robots_meta)
+        .into_iter()
+        // Check domain constraint
+        .filter(|link| !options.stay_on_domain || is_same_domain(&link.url, &ctx.base_domain))
+        .filter(|link| ctx.scope.allows(&link.url))
+        .collect();
+
+    // Skip links already visited or queued, the storage only checks those
+    // the seen filter has no room for
+    let mut claimed = Vec::new();
+    let mut unknown = Vec::new();
+    for link in &links {
+        let url = link.url.to_string();
+        match ctx.seen.claim(&url) {
+            Some(true) => claimed.push(url),
+            Some(false) => {}
+            None => unknown.push(url),
+        }
+    }
+    let mut new_urls: HashSet<String> = claimed.iter().cloned().collect();
+    if !unknown.is_empty() {
+        match storage::call(db, move |db| db.filter_new_urls(&unknown)).await {
+            Ok(urls) => new_urls.extend(urls),
             Err(e) => {
-                if args.verbose {
-                    eprintln!("{}", format!("[DB Error] {}", e).red());
+                release(ctx, &claimed);
+                record_error(ctx, parsed_url.as_str(), e.into(), "Can't check links in storage").await;
+                return;
+            }
+        }
+    }
+
+    let links: Vec<(Url, i64)> = links
+        .into_iter()
+        .filter(|link| new_urls.contains(link.url.as_str()))
+        .map(|Link { url, text }| {
+            let priority = score_url(&url, &text, depth + 1, &options.priority_keywords);
+            (url, priority)
+        })
+        .collect();
+
+    // Trap checks, queueing and trap counting happen under the detector's
+    // lock, so workers can't exceed a limit together and only queued links count
+    let detector = ctx.traps.clone();
+    let result = storage::call(db, move |db| {
+        let mut batch = detector.batch();
+        let mut to_queue = Vec::new();
+        let mut traps = Vec::new();
+        for (link, priority) in links {
+            // Skip URLs that look like crawl traps
+            match batch.check(&link) {
+                Some(trap) => traps.push((trap.pattern, trap.kind.to_string(), link.to_string())),
+                None => to_queue.push((link.to_string(), depth + 1, priority)),
+            }
+        }
+
+        let new_traps = db.record_traps(traps)?;
+        db.queue_urls(to_queue)?;
+        batch.record();
+        Ok::<_, storage::StorageError>(new_traps)
+    })
+    .await;
+
+    match result {
+        Ok(new_traps) => {
+            // Only the first URL of each pattern, later ones are skipped quietly
+            for (pattern, reason, example_url) in new_traps {
+                info!(pattern = %pattern, reason = %reason, example = %example_url, "Crawl trap");
+            }
+        }
+        Err(e) => {
+            release(ctx, &claimed);
+            record_error(ctx, parsed_url.as_str(), e.into(), "Can't queue links").await;
+        }
+    }
+}
+
+/// Forget links claimed in the seen filter that couldn't be queued, so a later page queues them
+fn release(ctx: &CrawlContext, claimed: &[String]) {
+    for url in claimed {
+        ctx.seen.release(url);
+    }
+}
+
+/// Extract emails, phones and images from a page and store them
+async fn store_findings(
+    ctx: &CrawlContext,
+    client: &Client,
+    url: &str,
+    parsed_url: &Url,
+    html: &str,
+    process_images: bool,
+) {
+    let db = &ctx.db;
+    let options = &ctx.options;
+
+    // Extract emails
+    let emails = options.extractor.emails_in(html);
+    if !emails.is_empty() {
+        let found = emails.len();
+        let source_url = url.to_string();
+        let stored = emails.clone();
+        match storage::call(db, move |db| db.insert_emails(stored, &source_url)).await {
+            Ok(new_emails) => {
+                info!(found, new = new_emails, "Found emails");
+                ctx.stats.add_emails(new_emails as u64);
+                metrics::FINDINGS_INSERTED.with_label_values(&["email"]).inc_by(new_emails as u64);
+                for email in emails {
+                    ctx.events.emit(CrawlEvent::EmailFound { email, url: url.to_string() }).await;
                 }
             }
+            Err(e) => record_error(ctx, url, e.into(), "Can't store emails").await,
         }
     }
 
+    // Extract phones
+    let phones = options.extractor.phones_in(html);
     if !phones.is_empty() {
-        println!("{}", format!("Found {} phones ({} new) on {}", phones.len(), new_phones, url).cyan());
+        let found = phones.len();
+        let source_url = url.to_string();
+        let stored = phones.clone();
+        match storage::call(db, move |db| db.insert_phones(stored, &source_url)).await {
+            Ok(new_phones) => {
+                info!(found, new = new_phones, "Found phones");
+                ctx.stats.add_phones(new_phones as u64);
+                metrics::FINDINGS_INSERTED.with_label_values(&["phone"]).inc_by(new_phones as u64);
+                for phone in phones {
+                    ctx.events.emit(CrawlEvent::PhoneFound { phone, url: url.to_string() }).await;
+                }
+            }
+            Err(e) => record_error(ctx, url, e.into(), "Can't store phones").await,
+        }
     }
 
-    // Extract and process images if enabled
-    if args.extract_images {
-        let image_urls = ImageProcessor::extract_image_urls(&html, &parsed_url);
+    // Extract and process images if enabled (a duplicate's images were already seen)
+    if options.extract_images && process_images {
+        let image_urls = ImageProcessor::extract_image_urls(html, parsed_url);
         
-        if args.verbose {
-            println!("{}", format!("[Images] Found {} image URLs on {}", image_urls.len(), url).blue());
-        }
+        debug!(images = image_urls.len(), "Found image URLs");
         
         if !image_urls.is_empty() {
             let processor = ImageProcessor::new(
-                &args.faces_dir,
-                &args.yolo_model,
-                args.min_image_width,
-                args.min_image_height,
+                &options.faces_dir,
+                &options.yolo_model,
+                options.min_image_width,
+                options.min_image_height,
             );
             
             for img_url in image_urls {
-                if args.verbose {
-                    println!("{}", format!("[Image] Processing: {}", img_url).blue());
+                debug!(image = %img_url, "Processing image");
+                match processor.process_image(client, &img_url, url, db, &ctx.events).await {
+                    Ok(true) => {
+                        ctx.stats.add_image();
+                        metrics::FINDINGS_INSERTED.with_label_values(&["image"]).inc();
+                    }
+                    Ok(false) => {}
+                    Err(e) => record_error(ctx, img_url.as_str(), e, "Image processing failed").await,
                 }
-                let _ = processor.process_image(&client, &img_url, db, args.verbose).await;
             }
         }
     }
+}
 
-    // Check depth limit
-    let should_follow_links = args.depth == 0 || depth < args.depth;
-    
-    if should_follow_links {
-        // Extract and queue new links
-        let links = extract_links(&html, &parsed_url);
-        
-        for link in links {
-            // Check domain constraint
-            if args.stay_on_domain && !is_same_domain(&link, base_domain) {
-                continue;
-            }
+/// Log, count and store a failure while crawling `url`
+async fn record_error(ctx: &CrawlContext, url: &str, e: CrawlError, context: &str) {
+    let kind = e.kind();
+    match e {
+        CrawlError::Db(_) | CrawlError::Io(_) => error!(kind, url, error = %e, "{}", context),
+        _ => warn!(kind, url, error = %e, "{}", context),
+    }
+    ctx.stats.add_error(kind);
+    let url = url.to_string();
+    if let Err(e) = storage::call(&ctx.db, move |db| db.record_error(&url, &e)).await {
+        error!(error = %e, "Can't record error");
+    }
+}
 
-            let link_str = link.to_string();
-            
-            // Check if already visited before queuing
-            if !db.is_visited(&link_str).unwrap_or(true) {
-                let _ = db.queue_url(&link_str, depth + 1);
-            }
-        }
+/// Record a page that couldn't be fetched and tell subscribers
+async fn record_page_failure(ctx: &CrawlContext, url: &str, e: CrawlError, context: &str) {
+    let failed = CrawlEvent::PageFailed {
+        url: url.to_string(),
+        kind: e.kind().to_string(),
+        message: e.to_string(),
+        retryable: e.is_retryable(),
+    };
+    record_error(ctx, url, e, context).await;
+    ctx.events.emit(failed).await;
+}
+
+/// Count a crawled page and its size against the host's budget
+async fn add_host_usage(ctx: &CrawlContext, url: &str, host: String, bytes: u64) {
+    if let Err(e) = storage::call(&ctx.db, move |db| db.add_host_usage(&host, bytes)).await {
+        record_error(ctx, url, e.into(), "Can't update host budget").await;
     }
 }
 
-async fn fetch_page(client: &Client, url: &Url) -> Result<String, reqwest::Error> {
-    let response = client
+/// A fetched page body with its response headers
+struct FetchedPage {
+    status: StatusCode,
+    body: String,
+    headers: HeaderMap,
+    /// Raw exchange for archiving, only kept when the body was downloaded
+    exchange: Option<HttpExchange>,
+}
+
+/// Fetch a page, failing with `BodyTooLarge` past `max_body_size` bytes (0 = unlimited)
+///
+/// The user agent is set on the request itself so the archived request shows it.
+async fn fetch_page(
+    client: &Client,
+    url: &Url,
+    user_agent: &str,
+    max_body_size: u64,
+) -> Result<FetchedPage, CrawlError> {
+    let request = client
         .get(url.as_str())
+        .header(USER_AGENT, user_agent)
         .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
         .header("Accept-Language", "en-US,en;q=0.5")
         .header("Accept-Encoding", "gzip, deflate, br")
         .header("Connection", "keep-alive")
         .header("Upgrade-Insecure-Requests", "1")
-        .send()
-        .await?;
-    
+        .build()?;
+    let request_headers = request.headers().clone();
+    let mut response = client.execute(request).await?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+
     // Only process HTML content
-    if let Some(content_type) = response.headers().get("content-type") {
+    if let Some(content_type) = headers.get("content-type") {
         if let Ok(ct) = content_type.to_str() {
             if !ct.contains("text/html") && !ct.contains("text/plain") {
-                return Ok(String::new());
+                return Ok(FetchedPage { status, body: String::new(), headers, exchange: None });
             }
         }
     }
 
-    response.text().await
+    let version = response.version();
+    let remote_addr = response.remote_addr();
+    let too_large = |size: u64| max_body_size > 0 && size > max_body_size;
+    if response.content_length().is_some_and(too_large) {
+        return Err(CrawlError::BodyTooLarge { limit: max_body_size });
+    }
+    // Without a length, stop reading as soon as the limit is passed
+    let mut raw = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        raw.extend_from_slice(&chunk);
+        if too_large(raw.len() as u64) {
+            return Err(CrawlError::BodyTooLarge { limit: max_body_size });
+        }
+    }
+    let body = decode_body(&headers, &raw);
+
+    Ok(FetchedPage {
+        status,
+        body,
+        exchange: Some(HttpExchange {
+            url: url.to_string(),
+            request_headers,
+            status,
+            version,
+            response_headers: headers.clone(),
+            remote_addr,
+            body: raw,
+        }),
+        headers,
+    })
+}
+
+/// Decode a body with the charset from its content type (UTF-8 by default)
+pub fn decode_body(headers: &HeaderMap, raw: &[u8]) -> String {
+    let encoding = headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|ct| ct.split(';').find_map(|param| param.trim().strip_prefix("charset=")))
+        .and_then(|charset| encoding_rs::Encoding::for_label(charset.trim_matches('"').as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(raw).0.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDb;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `/` with an email and a link to `/down`, which answers 503
+    async fn serve_site() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let (status, body) = if request.starts_with("GET / ") {
+                    ("200 OK", "<a href=\"/down\">down</a> contact@a.fr")
+                } else {
+                    ("503 Service Unavailable", "down")
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let url = serve_site().await;
+        let crawler = CrawlerBuilder::new(&url).workers(1).build().unwrap();
+        let events = crawler.subscribe(1);
+        let consumer = tokio::spawn(events.collect::<Vec<_>>());
+        crawler.run().await.unwrap();
+        let events = consumer.await.unwrap();
+
+        let down = format!("{}down", url);
+        assert_eq!(events[0], CrawlEvent::PageFetched { url: url.clone(), status: 200, bytes: 37, depth: 1 });
+        assert!(events.contains(&CrawlEvent::EmailFound { email: "contact@a.fr".to_string(), url: url.clone() }));
+        assert!(events.contains(&CrawlEvent::PageFetched { url: down.clone(), status: 503, bytes: 4, depth: 2 }));
+        assert!(!events.iter().any(|event| matches!(event, CrawlEvent::PageFailed { .. })));
+        let finished = CrawlEvent::CrawlFinished { run: 1, stop_reason: "queue_exhausted".to_string(), pages: 2 };
+        assert_eq!(events.last(), Some(&finished));
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_event() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let crawler = CrawlerBuilder::new(&url).workers(1).build().unwrap();
+        let events = crawler.subscribe(1);
+        let consumer = tokio::spawn(events.collect::<Vec<_>>());
+        crawler.run().await.unwrap();
+        let events = consumer.await.unwrap();
+
+        assert!(matches!(
+            &events[0],
+            CrawlEvent::PageFailed { url: failed, kind, retryable: true, .. } if *failed == url && kind == "connect"
+        ));
+        assert!(!events.iter().any(|event| matches!(event, CrawlEvent::PageFetched { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_archived_request_has_user_agent() {
+        let url = Url::parse(&serve_site().await).unwrap();
+        let user_agent = random_user_agent();
+        let client = create_stealth_client(user_agent, 5000, false).unwrap();
+        let page = fetch_page(&client, &url, user_agent, 0).await.unwrap();
+
+        let exchange = page.exchange.unwrap();
+        assert_eq!(exchange.request_headers.get(USER_AGENT).unwrap(), user_agent);
+    }
+
+    #[test]
+    fn test_builder() {
+        let db: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let crawler = CrawlerBuilder::new("https://a.fr/")
+            .storage(db.clone())
+            .workers(3)
+            .max_time(Duration::from_secs(90))
+            .build()
+            .unwrap();
+        assert_eq!(crawler.start_url(), "https://a.fr/");
+        assert_eq!(crawler.stats().workers(), 3);
+        assert_eq!(crawler.ctx.options.max_time, 90);
+        assert_eq!(crawler.storage().pending_count().unwrap(), 1);
+
+        // Memory storage keeps no runs to resume
+        assert!(CrawlerBuilder::resume(None).storage(db).build().is_err());
+        assert!(CrawlerBuilder::new("not a url").build().is_err());
+
+        let builder = CrawlerBuilder::new("https://a.fr/").max_time(Duration::from_millis(500));
+        assert_eq!(builder.options.max_time, 1);
+        assert_eq!(builder.max_time(Duration::ZERO).options.max_time, 0);
+
+        let builder = CrawlerBuilder::new("https://a.fr/").timeout(Duration::from_micros(300));
+        assert_eq!(builder.options.timeout, 1);
+    }
+
+    #[test]
+    fn test_run_to_resume() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let first = db.begin_run("[\"https://a.fr/\"]", "{}").unwrap();
+        let second = db.for_run(db.begin_run("[\"https://a.fr/\"]", "{}").unwrap());
+        second.finish_run("max_pages", 10, 1).unwrap();
+        assert_eq!(run_to_resume(&db, None).unwrap().id, second.run_id());
+
+        // A finished run is only resumed when asked for
+        second.finish_run("queue_exhausted", 20, 2).unwrap();
+        let error = run_to_resume(&db, None).unwrap_err();
+        assert!(error.to_string().contains("already finished"), "{}", error);
+        assert_eq!(run_to_resume(&db, Some(second.run_id())).unwrap().id, second.run_id());
+        assert_eq!(run_to_resume(&db, Some(first)).unwrap().id, first);
+    }
 }
@@ -1,277 +1,1123 @@
-use rusqlite::{Connection, Result, params};
-use std::sync::Mutex;
+use crate::error::CrawlError;
+use crate::metrics;
+use crate::migrations::{self, SchemaError};
+use crate::storage::RunRecord;
+use crate::strategy::CrawlStrategy;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{ffi, params, Connection, OpenFlags, OptionalExtension, Result};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+/// Maximum number of queued writes committed in a single transaction
+const WRITE_BATCH_SIZE: usize = 512;
+
+/// Number of read-only connections
+const READER_COUNT: usize = 4;
+
+/// How long a connection waits on a locked database before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A write executed by the writer thread, inside a batch transaction
+trait WriteJob: Send {
+    /// Run the write, returns whether it succeeded
+    fn run(&mut self, conn: &Connection) -> bool;
+
+    /// Deliver the write's result once its batch is committed, or the error that failed the batch
+    fn complete(self: Box<Self>, batch_error: Option<&rusqlite::Error>);
+}
+
+/// A write closure and where to send its result
+struct Job<T, F> {
+    write: Option<F>,
+    result: Option<Result<T>>,
+    reply: mpsc::SyncSender<Result<T>>,
+}
+
+impl<T, F> WriteJob for Job<T, F>
+where
+    F: FnOnce(&Connection) -> Result<T> + Send,
+    T: Send,
+{
+    fn run(&mut self, conn: &Connection) -> bool {
+        let result = self.write.take().map_or_else(|| Err(failure("write already run")), |write| write(conn));
+        let ok = result.is_ok();
+        self.result = Some(result);
+        ok
+    }
+
+    fn complete(self: Box<Self>, batch_error: Option<&rusqlite::Error>) {
+        let result = match (batch_error, self.result) {
+            (Some(e), _) => Err(failure(&format!("batch transaction failed: {}", e))),
+            (None, Some(result)) => result,
+            (None, None) => Err(failure("write was not run")),
+        };
+        let _ = self.reply.send(result);
+    }
+}
+
+/// SQLite storage in WAL mode
+///
+/// All writes go through a dedicated writer thread that groups concurrently
+/// queued writes into one transaction. Reads use a small pool of separate
+/// connections, which WAL lets run alongside the writer. Every method blocks,
+/// async code should go through [`crate::storage::call`].
+///
+/// A `Database` is a handle on one run: queue, visited, findings and budget
+/// operations apply to it. [`Database::new`] opens a handle on no run yet and
+/// [`Database::for_run`] gives handles on a run sharing the same connections.
 pub struct Database {
-    conn: Mutex<Connection>,
+    connections: Arc<Connections>,
+    run_id: i64,
+}
+
+/// Writer and readers shared by every handle on a database
+struct Connections {
+    writer: Mutex<Sender<Box<dyn WriteJob>>>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
 }
 
 impl Database {
-    /// Create or open a SQLite database
-    pub fn new(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        let db = Database {
-            conn: Mutex::new(conn),
-        };
-        db.init()?;
-        Ok(db)
-    }
-
-    /// Initialize the database schema
-    fn init(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        
-        // Emails table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS emails (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                email TEXT NOT NULL,
-                source_url TEXT NOT NULL,
-                found_at TEXT DEFAULT CURRENT_TIMESTAMP,
-                UNIQUE(email, source_url)
-            )",
-            [],
-        )?;
-        
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_email ON emails(email)",
-            [],
-        )?;
-
-        // URL queue table for persistence
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS url_queue (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                url TEXT NOT NULL UNIQUE,
-                depth INTEGER NOT NULL,
-                status TEXT DEFAULT 'pending'
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_queue_status ON url_queue(status)",
-            [],
-        )?;
-
-        // Visited URLs table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS visited (
-                url TEXT PRIMARY KEY
-            )",
-            [],
-        )?;
-
-        // Phones table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS phones (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                phone TEXT NOT NULL,
-                source_url TEXT NOT NULL,
-                found_at TEXT DEFAULT CURRENT_TIMESTAMP,
-                UNIQUE(phone, source_url)
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_phone ON phones(phone)",
-            [],
-        )?;
-
-        // Images table (for face detection)
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS images (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                uuid TEXT NOT NULL UNIQUE,
-                source_url TEXT NOT NULL,
-                found_at TEXT DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-        
-        Ok(())
-    }
-
-    /// Insert an email with its source URL (ignores duplicates)
-    pub fn insert_email(&self, email: &str, source_url: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let result = conn.execute(
-            "INSERT OR IGNORE INTO emails (email, source_url) VALUES (?1, ?2)",
-            params![email, source_url],
-        )?;
-        Ok(result > 0)
-    }
-
-    /// Add URL to queue (ignores if already exists)
-    pub fn queue_url(&self, url: &str, depth: u32) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let result = conn.execute(
-            "INSERT OR IGNORE INTO url_queue (url, depth, status) VALUES (?1, ?2, 'pending')",
-            params![url, depth],
-        )?;
-        Ok(result > 0)
-    }
-
-    /// Get next pending URL from queue
-    pub fn pop_url(&self) -> Result<Option<(String, u32)>> {
-        let conn = self.conn.lock().unwrap();
-        
-        let result: Option<(i64, String, u32)> = conn.query_row(
-            "SELECT id, url, depth FROM url_queue WHERE status = 'pending' LIMIT 1",
-            [],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-        ).ok();
-
-        if let Some((id, url, depth)) = result {
+    /// Create or open a SQLite database, migrating it to the latest schema
+    pub fn new(path: &str) -> std::result::Result<Self, SchemaError> {
+        let mut conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrations::migrate(&mut conn)?;
+
+        let mut readers = Vec::with_capacity(READER_COUNT);
+        for _ in 0..READER_COUNT {
+            let reader = Connection::open(path)?;
+            reader.busy_timeout(BUSY_TIMEOUT)?;
+            readers.push(Mutex::new(reader));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("db-writer".to_string())
+            .spawn(move || writer_loop(conn, receiver))
+            .map_err(|e| SchemaError::Sqlite(failure(&format!("failed to start writer: {}", e))))?;
+
+        let connections = Connections { writer: Mutex::new(sender), readers, next_reader: AtomicUsize::new(0) };
+        Ok(Database { connections: Arc::new(connections), run_id: 0 })
+    }
+
+    /// A handle on run `id`, sharing this database's connections
+    pub fn for_run(&self, id: i64) -> Database {
+        Database { connections: self.connections.clone(), run_id: id }
+    }
+
+    /// Open a database read-only for reporting, it must be at the latest schema
+    pub fn open_read_only(path: &str) -> std::result::Result<Connection, SchemaError> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        let found = migrations::check_version(&conn)?;
+        let latest = migrations::latest_version();
+        if found < latest {
+            return Err(SchemaError::Outdated { found, latest });
+        }
+        Ok(conn)
+    }
+
+    /// Id of the run this handle works on, 0 for none
+    pub fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    /// Record a new run with its seeds and arguments (JSON), returns its id
+    pub fn begin_run(&self, seeds: &str, args: &str) -> Result<i64> {
+        let seeds = seeds.to_string();
+        let args = args.to_string();
+        self.write(move |conn| {
             conn.execute(
-                "UPDATE url_queue SET status = 'processing' WHERE id = ?1",
-                params![id],
+                "INSERT INTO runs (seeds, args) VALUES (?1, ?2)",
+                params![seeds, args],
             )?;
-            Ok(Some((url, depth)))
-        } else {
-            Ok(None)
+            Ok(conn.last_insert_rowid())
+        })
+    }
+
+    /// A recorded run, the latest one if no id is given
+    pub fn find_run(&self, id: Option<i64>) -> Result<Option<RunRecord>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, seeds, args, stop_reason, elapsed_ms FROM runs
+                 WHERE ?1 IS NULL OR id = ?1
+                 ORDER BY id DESC LIMIT 1",
+            )?;
+            let mut rows = stmt.query(params![id])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(RunRecord {
+                    id: row.get(0)?,
+                    seeds: row.get(1)?,
+                    args: row.get(2)?,
+                    stop_reason: row.get(3)?,
+                    elapsed_ms: row.get(4)?,
+                })),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Record the time spent and pages crawled so far, kept if the crawl is interrupted
+    pub fn save_progress(&self, elapsed_ms: u64, pages: u64) -> Result<()> {
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            conn.execute(
+                "UPDATE runs SET elapsed_ms = ?2, pages = ?3 WHERE id = ?1",
+                params![run_id, elapsed_ms as i64, pages as i64],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Record the end of the run with its counters
+    pub fn finish_run(&self, stop_reason: &str, elapsed_ms: u64, pages: u64) -> Result<()> {
+        let run_id = self.run_id();
+        let stop_reason = stop_reason.to_string();
+        self.write(move |conn| {
+            conn.execute(
+                "UPDATE runs SET
+                    finished_at = CURRENT_TIMESTAMP,
+                    stop_reason = ?2,
+                    elapsed_ms = ?3,
+                    pages = ?4
+                 WHERE id = ?1",
+                params![run_id, stop_reason, elapsed_ms as i64, pages as i64],
+            )?;
+            update_run_counts(conn, run_id)
+        })
+    }
+
+    /// Recount the run's findings after they changed outside a crawl
+    pub fn refresh_run_counts(&self) -> Result<()> {
+        let run_id = self.run_id();
+        self.write(move |conn| update_run_counts(conn, run_id))
+    }
+
+    /// Queue a write on the writer thread and wait for its batch to commit
+    fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _timer = metrics::DB_WRITE_DURATION.start_timer();
+        let (reply, response) = mpsc::sync_channel(1);
+        let job = Box::new(Job { write: Some(f), result: None, reply });
+
+        self.connections
+            .writer
+            .lock()
+            .unwrap()
+            .send(job)
+            .map_err(|_| failure("database writer stopped"))?;
+        response
+            .recv()
+            .unwrap_or_else(|_| Err(failure("database writer stopped")))
+    }
+
+    /// Run a query on a free read connection
+    fn read<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let connections = &self.connections;
+        for reader in &connections.readers {
+            if let Ok(conn) = reader.try_lock() {
+                return f(&conn);
+            }
         }
+        let index = connections.next_reader.fetch_add(1, Ordering::Relaxed) % connections.readers.len();
+        let conn = connections.readers[index].lock().unwrap();
+        f(&conn)
+    }
+
+    /// Record emails seen on a page, returns how many were not yet seen on it
+    pub fn insert_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize> {
+        let source_url = source_url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| record_sightings(conn, FindingKind::Email, run_id, &source_url, &emails))
+    }
+
+    /// Add URLs to queue as (url, depth, priority), returns how many were new
+    pub fn queue_urls(&self, urls: Vec<(String, u32, i64)>) -> Result<usize> {
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "INSERT OR IGNORE INTO url_queue (run_id, url, depth, status, priority)
+                 VALUES (?1, ?2, ?3, 'pending', ?4)",
+            )?;
+            let mut inserted = 0;
+            for (url, depth, priority) in &urls {
+                inserted += stmt.execute(params![run_id, url, depth, priority])?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    /// Get next pending URL from queue, in the order of the crawl strategy
+    pub fn pop_url(&self, strategy: CrawlStrategy) -> Result<Option<(String, u32)>> {
+        // Select and claim on the writer so two workers never get the same URL
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let query = format!(
+                "SELECT id, url, depth FROM url_queue
+                 WHERE run_id = ?1 AND status = 'pending' ORDER BY {} LIMIT 1",
+                strategy.order_by()
+            );
+            let result: Option<(i64, String, u32)> = conn.query_row(
+                &query,
+                params![run_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            ).ok();
+
+            if let Some((id, url, depth)) = result {
+                conn.execute(
+                    "UPDATE url_queue SET status = 'processing' WHERE id = ?1",
+                    params![id],
+                )?;
+                Ok(Some((url, depth)))
+            } else {
+                Ok(None)
+            }
+        })
     }
 
     /// Mark URL as completed
     pub fn complete_url(&self, url: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE url_queue SET status = 'done' WHERE url = ?1",
-            params![url],
-        )?;
-        Ok(())
+        self.set_url_status(url, "done")
+    }
+
+    /// Park a URL whose host is out of budget until the next resume
+    pub fn defer_url(&self, url: &str) -> Result<()> {
+        self.set_url_status(url, "deferred")
+    }
+
+    fn set_url_status(&self, url: &str, status: &'static str) -> Result<()> {
+        let url = url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            conn.execute(
+                "UPDATE url_queue SET status = ?3 WHERE run_id = ?1 AND url = ?2",
+                params![run_id, url, status],
+            )?;
+            Ok(())
+        })
     }
 
     /// Check if URL was already visited
     pub fn is_visited(&self, url: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let count: u32 = conn.query_row(
-            "SELECT COUNT(*) FROM visited WHERE url = ?1",
-            params![url],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
+        self.read(|conn| {
+            let count: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM visited WHERE run_id = ?1 AND url = ?2",
+                params![self.run_id(), url],
+                |row| row.get(0),
+            )?;
+            Ok(count > 0)
+        })
+    }
+
+    /// Keep only URLs that are neither visited nor already queued
+    pub fn filter_new_urls(&self, urls: &[String]) -> Result<HashSet<String>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT EXISTS(SELECT 1 FROM visited WHERE run_id = ?1 AND url = ?2)
+                     OR EXISTS(SELECT 1 FROM url_queue WHERE run_id = ?1 AND url = ?2)",
+            )?;
+            let run_id = self.run_id();
+            let mut new_urls = HashSet::new();
+            for url in urls {
+                let known: bool = stmt.query_row(params![run_id, url], |row| row.get(0))?;
+                if !known {
+                    new_urls.insert(url.clone());
+                }
+            }
+            Ok(new_urls)
+        })
     }
 
     /// Mark URL as visited
     pub fn mark_visited(&self, url: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR IGNORE INTO visited (url) VALUES (?1)",
-            params![url],
-        )?;
-        Ok(())
+        let url = url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO visited (run_id, url) VALUES (?1, ?2)",
+                params![run_id, url],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Call `f` with every queued or visited URL of the run and whether it was visited
+    pub fn for_each_seen_url(&self, f: &mut dyn FnMut(&str, bool)) -> Result<()> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT url, 1 FROM visited WHERE run_id = ?1
+                 UNION ALL
+                 SELECT url, 0 FROM url_queue
+                 WHERE run_id = ?1 AND url NOT IN (SELECT url FROM visited WHERE run_id = ?1)",
+            )?;
+            let mut rows = stmt.query(params![self.run_id()])?;
+            while let Some(row) = rows.next()? {
+                let url: String = row.get(0)?;
+                f(&url, row.get(1)?);
+            }
+            Ok(())
+        })
     }
 
     /// Get count of pending URLs
     pub fn pending_count(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(*) FROM url_queue WHERE status = 'pending'",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
-    }
-
-    /// Get count of processing URLs
-    pub fn processing_count(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(*) FROM url_queue WHERE status = 'processing'",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
-    }
-
-    /// Reset processing URLs to pending (for resume)
+        self.count("SELECT COUNT(*) FROM url_queue WHERE run_id = ?1 AND status = 'pending'")
+    }
+
+    /// Reset processing and deferred URLs to pending (for resume)
     pub fn reset_processing(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count = conn.execute(
-            "UPDATE url_queue SET status = 'pending' WHERE status = 'processing'",
-            [],
-        )?;
-        Ok(count as u64)
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let count = conn.execute(
+                "UPDATE url_queue SET status = 'pending'
+                 WHERE run_id = ?1 AND status IN ('processing', 'deferred')",
+                params![run_id],
+            )?;
+            Ok(count as u64)
+        })
     }
 
-    /// Clear queue (for fresh start)
-    pub fn clear_queue(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM url_queue", [])?;
-        conn.execute("DELETE FROM visited", [])?;
-        Ok(())
+    /// Run a single-value `COUNT` query for the run (bound as `?1`)
+    fn count(&self, query: &str) -> Result<u64> {
+        self.read(|conn| conn.query_row(query, params![self.run_id()], |row| row.get(0)))
     }
 
-    /// Get total count of unique emails
+    /// Get count of unique emails in the run
     pub fn get_email_count(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(DISTINCT email) FROM emails",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
+        self.count(
+            "SELECT COUNT(DISTINCT s.email_id) FROM email_sightings s
+             JOIN pages p ON p.id = s.page_id WHERE p.run_id = ?1",
+        )
     }
 
-    /// Get total count of email entries
+    /// Get count of email sightings in the run
     pub fn get_total_entries(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(*) FROM emails",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
-    }
-
-    /// Get statistics about the database
-    pub fn get_stats(&self) -> Result<(u64, u64)> {
-        let unique = self.get_email_count()?;
-        let total = self.get_total_entries()?;
-        Ok((unique, total))
+        self.count(
+            "SELECT COUNT(*) FROM email_sightings s
+             JOIN pages p ON p.id = s.page_id WHERE p.run_id = ?1",
+        )
     }
 
-    /// Insert a phone number with its source URL (ignores duplicates)
-    pub fn insert_phone(&self, phone: &str, source_url: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let result = conn.execute(
-            "INSERT OR IGNORE INTO phones (phone, source_url) VALUES (?1, ?2)",
-            params![phone, source_url],
-        )?;
-        Ok(result > 0)
+    /// Record phone numbers seen on a page, returns how many were not yet seen on it
+    pub fn insert_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize> {
+        let source_url = source_url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| record_sightings(conn, FindingKind::Phone, run_id, &source_url, &phones))
     }
 
-    /// Get total count of unique phones
+    /// Get count of unique phones in the run
     pub fn get_phone_count(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(DISTINCT phone) FROM phones",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
+        self.count(
+            "SELECT COUNT(DISTINCT s.phone_id) FROM phone_sightings s
+             JOIN pages p ON p.id = s.page_id WHERE p.run_id = ?1",
+        )
     }
 
     /// Insert an image with its UUID and source URL
-    pub fn insert_image(&self, uuid: &str, source_url: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let result = conn.execute(
-            "INSERT OR IGNORE INTO images (uuid, source_url) VALUES (?1, ?2)",
-            params![uuid, source_url],
-        )?;
-        Ok(result > 0)
+    pub fn insert_image(&self, uuid: &str, page_url: &str, image_url: &str) -> Result<bool> {
+        let uuid = uuid.to_string();
+        let page_url = page_url.to_string();
+        let image_url = image_url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let page_id = page_id(conn, run_id, &page_url)?;
+            let result = conn.execute(
+                "INSERT OR IGNORE INTO images (uuid, page_id, image_url) VALUES (?1, ?2, ?3)",
+                params![uuid, page_id, image_url],
+            )?;
+            Ok(result > 0)
+        })
     }
 
-    /// Get total count of images
+    /// Get count of images in the run
     pub fn get_image_count(&self) -> Result<u64> {
-        let conn = self.conn.lock().unwrap();
-        let count: u64 = conn.query_row(
-            "SELECT COUNT(*) FROM images",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count)
+        self.count("SELECT COUNT(*) FROM images i JOIN pages p ON p.id = i.page_id WHERE p.run_id = ?1")
+    }
+
+    /// Record trapped URL patterns as (pattern, reason, example_url)
+    ///
+    /// Returns the traps seen for the first time.
+    pub fn record_traps(&self, traps: Vec<(String, String, String)>) -> Result<Vec<(String, String, String)>> {
+        self.write(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "INSERT INTO traps (pattern, reason, example_url) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(pattern, reason) DO UPDATE SET
+                    hits = hits + 1,
+                    last_seen = CURRENT_TIMESTAMP
+                 RETURNING hits",
+            )?;
+            let mut new_traps = Vec::new();
+            for trap in traps {
+                let hits: u64 = stmt.query_row(params![trap.0, trap.1, trap.2], |row| row.get(0))?;
+                if hits == 1 {
+                    new_traps.push(trap);
+                }
+            }
+            Ok(new_traps)
+        })
+    }
+
+    /// Get total count of trapped URL patterns (all runs)
+    pub fn get_trap_count(&self) -> Result<u64> {
+        self.read(|conn| conn.query_row("SELECT COUNT(*) FROM traps", [], |row| row.get(0)))
+    }
+
+    /// Record a failed URL with the kind of failure
+    pub fn record_error(&self, url: &str, error: &CrawlError) -> Result<()> {
+        let (url, kind, message, retryable) = (url.to_string(), error.kind(), error.to_string(), error.is_retryable());
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO errors (run_id, url, kind, message, retryable) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![run_id, url, kind, message, retryable],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Queue again the URLs whose errors can be retried, returns how many were requeued
+    ///
+    /// They are no longer visited, and their errors are marked as retried so a
+    /// later resume doesn't requeue them again.
+    pub fn requeue_failed(&self) -> Result<u64> {
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let failed = "SELECT url FROM errors WHERE run_id = ?1 AND retryable = 1 AND retried = 0";
+            let count = conn.execute(
+                &format!("UPDATE url_queue SET status = 'pending' WHERE run_id = ?1 AND url IN ({})", failed),
+                params![run_id],
+            )?;
+            conn.execute(&format!("DELETE FROM visited WHERE run_id = ?1 AND url IN ({})", failed), params![run_id])?;
+            conn.execute(
+                "UPDATE errors SET retried = 1 WHERE run_id = ?1 AND retryable = 1 AND retried = 0",
+                params![run_id],
+            )?;
+            Ok(count as u64)
+        })
+    }
+
+    /// Get count of recorded errors in the run
+    pub fn get_error_count(&self) -> Result<u64> {
+        self.count("SELECT COUNT(*) FROM errors WHERE run_id = ?1")
+    }
+
+    /// Insert a fetched page with its HTTP status and fingerprint (hashes stored as signed 64-bit)
+    pub fn insert_page(
+        &self,
+        url: &str,
+        status: Option<u16>,
+        fingerprint: Option<(u64, u64)>,
+        duplicate_of: Option<&str>,
+    ) -> Result<()> {
+        let url = url.to_string();
+        let duplicate_of = duplicate_of.map(str::to_string);
+        let (content_hash, simhash) = match fingerprint {
+            Some((hash, simhash)) => (Some(hash as i64), Some(simhash as i64)),
+            None => (None, None),
+        };
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            // Findings stored first may already have created the page row
+            conn.execute(
+                "INSERT INTO pages (url, run_id, status, content_hash, simhash, duplicate_of)
+                 VALUES (?1, ?2, ?3, ?4, ?5, (SELECT id FROM pages WHERE run_id = ?2 AND url = ?6))
+                 ON CONFLICT(run_id, url) DO UPDATE SET
+                    status = excluded.status,
+                    content_hash = excluded.content_hash,
+                    simhash = excluded.simhash,
+                    duplicate_of = excluded.duplicate_of",
+                params![url, run_id, status, content_hash, simhash, duplicate_of],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get fingerprints of the run's original (non-duplicate) pages
+    pub fn get_page_fingerprints(&self) -> Result<Vec<(String, u64, u64)>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT url, content_hash, simhash FROM pages
+                 WHERE run_id = ?1 AND duplicate_of IS NULL AND content_hash IS NOT NULL",
+            )?;
+            let rows = stmt.query_map(params![self.run_id()], |row| {
+                let hash: i64 = row.get(1)?;
+                let simhash: i64 = row.get(2)?;
+                Ok((row.get(0)?, hash as u64, simhash as u64))
+            })?;
+            rows.collect()
+        })
+    }
+
+    /// Get count of pages flagged as duplicates in the run
+    pub fn get_duplicate_count(&self) -> Result<u64> {
+        self.count("SELECT COUNT(*) FROM pages WHERE run_id = ?1 AND duplicate_of IS NOT NULL")
+    }
+
+    /// Add a crawled page and its size to the host's budget usage
+    pub fn add_host_usage(&self, host: &str, bytes: u64) -> Result<()> {
+        let host = host.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO host_budget (run_id, host, pages, bytes) VALUES (?1, ?2, 1, ?3)
+                 ON CONFLICT(run_id, host) DO UPDATE SET pages = pages + 1, bytes = bytes + ?3",
+                params![run_id, host, bytes as i64],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get budget usage per host in the run as (host, pages, bytes)
+    pub fn get_host_usage(&self) -> Result<Vec<(String, u64, u64)>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare("SELECT host, pages, bytes FROM host_budget WHERE run_id = ?1")?;
+            let rows = stmt.query_map(params![self.run_id()], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })
+    }
+
+    /// Store a page's HTML, gzipped, for later re-extraction
+    pub fn store_page_content(&self, url: &str, html: &str) -> Result<()> {
+        // Compress on the calling thread, not on the writer
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let body = encoder
+            .write_all(html.as_bytes())
+            .and_then(|_| encoder.finish())
+            .map_err(|e| failure(&e.to_string()))?;
+        let url = url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| {
+            let page_id = page_id(conn, run_id, &url)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO page_contents (page_id, body) VALUES (?1, ?2)",
+                params![page_id, body],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// URLs of the run's pages with stored content
+    pub fn get_stored_pages(&self) -> Result<Vec<String>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT p.url FROM pages p JOIN page_contents c ON c.page_id = p.id
+                 WHERE p.run_id = ?1 ORDER BY p.id",
+            )?;
+            let rows = stmt.query_map(params![self.run_id()], |row| row.get(0))?;
+            rows.collect()
+        })
+    }
+
+    /// Stored HTML of a page of the run
+    pub fn get_page_content(&self, url: &str) -> Result<Option<String>> {
+        let body: Option<Vec<u8>> = self.read(|conn| {
+            conn.query_row(
+                "SELECT c.body FROM pages p JOIN page_contents c ON c.page_id = p.id
+                 WHERE p.run_id = ?1 AND p.url = ?2",
+                params![self.run_id(), url],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+
+        match body {
+            Some(body) => {
+                let mut html = String::new();
+                GzDecoder::new(body.as_slice())
+                    .read_to_string(&mut html)
+                    .map_err(|e| failure(&e.to_string()))?;
+                Ok(Some(html))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the run fetched a page
+    pub fn has_page(&self, url: &str) -> Result<bool> {
+        self.read(|conn| {
+            conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM pages WHERE run_id = ?1 AND url = ?2)",
+                params![self.run_id(), url],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Emails and phones seen on a page of the run
+    pub fn get_page_findings(&self, url: &str) -> Result<(Vec<String>, Vec<String>)> {
+        let emails = self.page_values(FindingKind::Email, url)?;
+        let phones = self.page_values(FindingKind::Phone, url)?;
+        Ok((emails, phones))
+    }
+
+    fn page_values(&self, kind: FindingKind, url: &str) -> Result<Vec<String>> {
+        let (table, column, sightings) = kind.tables();
+        self.read(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT v.{column} FROM {sightings} s
+                 JOIN {table} v ON v.id = s.{column}_id
+                 JOIN pages p ON p.id = s.page_id
+                 WHERE p.run_id = ?1 AND p.url = ?2
+                 ORDER BY v.{column}"
+            ))?;
+            let rows = stmt.query_map(params![self.run_id(), url], |row| row.get(0))?;
+            rows.collect()
+        })
+    }
+
+    /// Remove emails no longer found on a page, returns how many were removed
+    pub fn remove_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize> {
+        let source_url = source_url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| remove_sightings(conn, FindingKind::Email, run_id, &source_url, &emails))
+    }
+
+    /// Remove phone numbers no longer found on a page, returns how many were removed
+    pub fn remove_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize> {
+        let source_url = source_url.to_string();
+        let run_id = self.run_id();
+        self.write(move |conn| remove_sightings(conn, FindingKind::Phone, run_id, &source_url, &phones))
+    }
+}
+
+/// Kind of contact value recorded with its sightings
+#[derive(Debug, Clone, Copy)]
+enum FindingKind {
+    Email,
+    Phone,
+}
+
+impl FindingKind {
+    /// Value table, its value column and the sightings table
+    fn tables(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            FindingKind::Email => ("emails", "email", "email_sightings"),
+            FindingKind::Phone => ("phones", "phone", "phone_sightings"),
+        }
+    }
+}
+
+/// Store a run's email, phone and image counters
+fn update_run_counts(conn: &Connection, run_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE runs SET
+            emails = (SELECT COUNT(DISTINCT s.email_id) FROM email_sightings s
+                      JOIN pages p ON p.id = s.page_id WHERE p.run_id = ?1),
+            phones = (SELECT COUNT(DISTINCT s.phone_id) FROM phone_sightings s
+                      JOIN pages p ON p.id = s.page_id WHERE p.run_id = ?1),
+            images = (SELECT COUNT(*) FROM images i
+                      JOIN pages p ON p.id = i.page_id WHERE p.run_id = ?1)
+         WHERE id = ?1",
+        params![run_id],
+    )?;
+    Ok(())
+}
+
+/// Id of a page of a run, creating the row if the page is not recorded yet
+fn page_id(conn: &Connection, run_id: i64, url: &str) -> Result<i64> {
+    conn.prepare_cached("INSERT OR IGNORE INTO pages (run_id, url) VALUES (?1, ?2)")?
+        .execute(params![run_id, url])?;
+    conn.prepare_cached("SELECT id FROM pages WHERE run_id = ?1 AND url = ?2")?
+        .query_row(params![run_id, url], |row| row.get(0))
+}
+
+/// Record values seen on a page, keeping timestamps and occurrence counts current
+///
+/// Returns how many values had no sighting on this page yet.
+fn record_sightings(conn: &Connection, kind: FindingKind, run_id: i64, url: &str, values: &[String]) -> Result<usize> {
+    let (table, column, sightings) = kind.tables();
+    let page_id = page_id(conn, run_id, url)?;
+
+    let mut upsert_value = conn.prepare_cached(&format!(
+        "INSERT INTO {table} ({column}) VALUES (?1)
+         ON CONFLICT({column}) DO UPDATE SET last_seen = CURRENT_TIMESTAMP
+         RETURNING id"
+    ))?;
+    let mut url_known = conn.prepare_cached(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {sightings} s JOIN pages p ON p.id = s.page_id
+                       WHERE s.{column}_id = ?1 AND p.url = ?2)"
+    ))?;
+    let mut insert_sighting = conn.prepare_cached(&format!(
+        "INSERT OR IGNORE INTO {sightings} ({column}_id, page_id) VALUES (?1, ?2)"
+    ))?;
+    let mut touch_sighting = conn.prepare_cached(&format!(
+        "UPDATE {sightings} SET last_seen = CURRENT_TIMESTAMP WHERE {column}_id = ?1 AND page_id = ?2"
+    ))?;
+    let mut count_sighting = conn.prepare_cached(&format!(
+        "UPDATE {table} SET occurrences = occurrences + 1, url_count = url_count + ?2 WHERE id = ?1"
+    ))?;
+
+    let mut inserted = 0;
+    for value in values {
+        let value_id: i64 = upsert_value.query_row(params![value], |row| row.get(0))?;
+        let known: bool = url_known.query_row(params![value_id, url], |row| row.get(0))?;
+        if insert_sighting.execute(params![value_id, page_id])? > 0 {
+            count_sighting.execute(params![value_id, if known { 0 } else { 1 }])?;
+            inserted += 1;
+        } else {
+            touch_sighting.execute(params![value_id, page_id])?;
+        }
+    }
+    Ok(inserted)
+}
+
+/// Delete the sightings of values on a page and update their counts
+///
+/// Values left without any sighting are deleted.
+fn remove_sightings(conn: &Connection, kind: FindingKind, run_id: i64, url: &str, values: &[String]) -> Result<usize> {
+    let (table, column, sightings) = kind.tables();
+    let page_id: Option<i64> = conn
+        .prepare_cached("SELECT id FROM pages WHERE run_id = ?1 AND url = ?2")?
+        .query_row(params![run_id, url], |row| row.get(0))
+        .optional()?;
+    let Some(page_id) = page_id else {
+        return Ok(0);
+    };
+
+    let mut delete_sighting = conn.prepare_cached(&format!(
+        "DELETE FROM {sightings}
+         WHERE page_id = ?2 AND {column}_id = (SELECT id FROM {table} WHERE {column} = ?1)
+         RETURNING {column}_id"
+    ))?;
+    let mut url_known = conn.prepare_cached(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {sightings} s JOIN pages p ON p.id = s.page_id
+                       WHERE s.{column}_id = ?1 AND p.url = ?2)"
+    ))?;
+    let mut uncount_sighting = conn.prepare_cached(&format!(
+        "UPDATE {table} SET occurrences = occurrences - 1, url_count = url_count - ?2 WHERE id = ?1"
+    ))?;
+    let mut delete_unseen = conn.prepare_cached(&format!(
+        "DELETE FROM {table} WHERE id = ?1 AND occurrences <= 0"
+    ))?;
+
+    let mut removed = 0;
+    for value in values {
+        let value_id: Option<i64> = delete_sighting
+            .query_map(params![value, page_id], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        if let Some(value_id) = value_id {
+            let known: bool = url_known.query_row(params![value_id, url], |row| row.get(0))?;
+            uncount_sighting.execute(params![value_id, if known { 0 } else { 1 }])?;
+            delete_unseen.execute(params![value_id])?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Writer thread: commits queued writes in batches until the database is dropped
+fn writer_loop(conn: Connection, jobs: Receiver<Box<dyn WriteJob>>) {
+    while let Ok(first) = jobs.recv() {
+        let mut batch = vec![first];
+        while batch.len() < WRITE_BATCH_SIZE {
+            match jobs.try_recv() {
+                Ok(job) => batch.push(job),
+                Err(_) => break,
+            }
+        }
+
+        if let Err(e) = conn.execute_batch("BEGIN IMMEDIATE") {
+            for job in batch {
+                job.complete(Some(&e));
+            }
+            continue;
+        }
+        let mut batch_error = None;
+        for job in &mut batch {
+            if let Err(e) = run_in_savepoint(&conn, job.as_mut()) {
+                batch_error = Some(e);
+                break;
+            }
+        }
+        if batch_error.is_none() {
+            batch_error = conn.execute_batch("COMMIT").err();
+        }
+        if batch_error.is_some() {
+            let _ = conn.execute_batch("ROLLBACK");
+        }
+
+        for job in batch {
+            job.complete(batch_error.as_ref());
+        }
+    }
+}
+
+/// Run a job in its own savepoint, so a failed job leaves none of its writes in the batch
+fn run_in_savepoint(conn: &Connection, job: &mut dyn WriteJob) -> Result<()> {
+    conn.execute_batch("SAVEPOINT job")?;
+    if job.run(conn) {
+        conn.execute_batch("RELEASE job")
+    } else {
+        conn.execute_batch("ROLLBACK TO job; RELEASE job")
+    }
+}
+
+/// Build a generic SQLite error with a message
+fn failure(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_ERROR), Some(message.to_string()))
+}
+
+/// A fresh temporary database file for tests (WAL needs a real file)
+///
+/// The file and its WAL files are removed on drop, even when a test fails.
+/// Other storage files can use it too, see [`TempDb::with_extension`].
+/// Declare it before the handles on it so they are closed first.
+#[cfg(test)]
+pub(crate) struct TempDb {
+    path: String,
+}
+
+#[cfg(test)]
+impl TempDb {
+    pub fn new() -> Self {
+        TempDb::with_extension("db")
+    }
+
+    pub fn with_extension(extension: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("couscous-test-{}.{}", uuid::Uuid::new_v4(), extension));
+        TempDb { path: path.to_str().unwrap().to_string() }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Open the database, creating it on first use
+    pub fn open(&self) -> Database {
+        Database::new(&self.path).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path, suffix));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_queue_order_by_strategy() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+        db.queue_urls(vec![
+            ("https://a.fr/".to_string(), 1, 0),
+            ("https://a.fr/blog".to_string(), 2, -20),
+            ("https://a.fr/contact".to_string(), 2, 80),
+        ])
+        .unwrap();
+
+        assert_eq!(db.pop_url(CrawlStrategy::BestFirst).unwrap().unwrap().0, "https://a.fr/contact");
+        assert_eq!(db.pop_url(CrawlStrategy::Bfs).unwrap().unwrap().0, "https://a.fr/");
+        assert_eq!(db.pop_url(CrawlStrategy::Dfs).unwrap().unwrap().0, "https://a.fr/blog");
+        assert!(db.pop_url(CrawlStrategy::Bfs).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_batched_and_visible() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+        let db = Arc::new(db);
+
+        let handles: Vec<_> = (0..8)
+            .map(|worker| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    let emails = (0..50).map(|i| format!("user{}@w{}.fr", i, worker)).collect();
+                    db.insert_emails(emails, "https://a.fr/").unwrap()
+                })
+            })
+            .collect();
+        let inserted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+        assert_eq!(inserted, 400);
+        assert_eq!(db.get_email_count().unwrap(), 400);
+        assert_eq!(db.insert_emails(vec!["user0@w0.fr".to_string()], "https://a.fr/").unwrap(), 0);
+
+        db.mark_visited("https://a.fr/").unwrap();
+        db.queue_url("https://a.fr/queued", 1, 0).unwrap();
+        let urls = vec![
+            "https://a.fr/".to_string(),
+            "https://a.fr/queued".to_string(),
+            "https://a.fr/new".to_string(),
+        ];
+        let new_urls = db.filter_new_urls(&urls).unwrap();
+        assert_eq!(new_urls.len(), 1);
+        assert!(new_urls.contains("https://a.fr/new"));
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let db = Arc::new(db);
+
+        // A write failing after its first statement, batched with ones that succeed
+        let handles: Vec<_> = (0..8)
+            .map(|n| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    db.write(move |conn| {
+                        conn.execute("INSERT INTO runs (seeds, args) VALUES (?1, '{}')", params![n.to_string()])?;
+                        if n == 3 {
+                            return Err(failure("boom"));
+                        }
+                        Ok(())
+                    })
+                })
+            })
+            .collect();
+        let failed = handles.into_iter().map(|h| h.join().unwrap()).filter(Result::is_err).count();
+
+        assert_eq!(failed, 1);
+        let seeds: Vec<String> = db
+            .read(|conn| {
+                let mut stmt = conn.prepare("SELECT seeds FROM runs ORDER BY seeds")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .unwrap();
+        assert_eq!(seeds, ["0", "1", "2", "4", "5", "6", "7"]);
+    }
+
+    #[test]
+    fn test_sightings_update_counts() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+
+        let emails = vec!["x@a.fr".to_string()];
+        assert_eq!(db.insert_emails(emails.clone(), "https://a.fr/").unwrap(), 1);
+        assert_eq!(db.insert_emails(emails.clone(), "https://a.fr/").unwrap(), 0);
+        assert_eq!(db.insert_emails(emails.clone(), "https://a.fr/team").unwrap(), 1);
+
+        // The same URL in a later run is a new occurrence but not a new URL
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+        assert_eq!(db.insert_emails(emails, "https://a.fr/").unwrap(), 1);
+        db.insert_page("https://a.fr/", Some(200), Some((1, 1)), None).unwrap();
+
+        let (occurrences, url_count): (u64, u64) = db
+            .read(|conn| {
+                conn.query_row(
+                    "SELECT occurrences, url_count FROM emails WHERE email = 'x@a.fr'",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+            })
+            .unwrap();
+        assert_eq!((occurrences, url_count), (3, 2));
+        assert_eq!(db.get_email_count().unwrap(), 1);
+        assert_eq!(db.get_page_fingerprints().unwrap().len(), 1);
+
+        // An image belongs to the page that referenced it, not to a page of its own
+        assert!(db.insert_image("u1", "https://a.fr/", "https://a.fr/face.jpg").unwrap());
+        let images: Vec<(String, String)> = db
+            .read(|conn| {
+                let mut stmt = conn.prepare("SELECT p.url, i.image_url FROM images i JOIN pages p ON p.id = i.page_id")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect()
+            })
+            .unwrap();
+        assert_eq!(images, vec![("https://a.fr/".to_string(), "https://a.fr/face.jpg".to_string())]);
+        assert_eq!(db.count("SELECT COUNT(*) FROM pages WHERE run_id = ?1").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_page_store_and_removal() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+
+        db.store_page_content("https://a.fr/", "<p>x@a.fr</p>").unwrap();
+        assert_eq!(db.get_stored_pages().unwrap(), vec!["https://a.fr/".to_string()]);
+        assert_eq!(db.get_page_content("https://a.fr/").unwrap().as_deref(), Some("<p>x@a.fr</p>"));
+        assert!(db.get_page_content("https://a.fr/other").unwrap().is_none());
+
+        let emails = vec!["x@a.fr".to_string(), "y@a.fr".to_string()];
+        db.insert_emails(emails.clone(), "https://a.fr/").unwrap();
+        db.insert_emails(vec!["x@a.fr".to_string()], "https://a.fr/team").unwrap();
+        assert_eq!(db.get_page_findings("https://a.fr/").unwrap().0, emails);
+
+        assert_eq!(db.remove_emails(emails, "https://a.fr/").unwrap(), 2);
+        assert!(db.get_page_findings("https://a.fr/").unwrap().0.is_empty());
+        assert_eq!(db.get_email_count().unwrap(), 1);
+
+        // y@a.fr is gone, x@a.fr is still seen on /team
+        let remaining: Vec<(String, u64, u64)> = db
+            .read(|conn| {
+                let mut stmt = conn.prepare("SELECT email, occurrences, url_count FROM emails")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                rows.collect()
+            })
+            .unwrap();
+        assert_eq!(remaining, vec![("x@a.fr".to_string(), 1, 1)]);
+    }
+
+    #[test]
+    fn test_runs_are_isolated() {
+        let temp = TempDb::new();
+        let db = temp.open();
+
+        let first = db.for_run(db.begin_run("[\"https://a.fr/\"]", "{}").unwrap());
+        first.queue_url("https://a.fr/", 1, 0).unwrap();
+        first.mark_visited("https://a.fr/").unwrap();
+        first.insert_emails(vec!["a@a.fr".to_string()], "https://a.fr/").unwrap();
+        first.finish_run("queue_exhausted", 1500, 1).unwrap();
+
+        // A fresh run sees an empty queue and can find the same email again
+        let second = db.for_run(db.begin_run("[\"https://a.fr/\"]", "{}").unwrap());
+        assert_ne!(first.run_id(), second.run_id());
+        assert!(!second.is_visited("https://a.fr/").unwrap());
+        assert!(second.queue_url("https://a.fr/", 1, 0).unwrap());
+        assert_eq!(second.insert_emails(vec!["a@a.fr".to_string()], "https://a.fr/").unwrap(), 1);
+
+        // Handles on both runs work side by side
+        second.save_progress(700, 1).unwrap();
+        assert_eq!(db.find_run(Some(second.run_id())).unwrap().unwrap().elapsed_ms, 700);
+        let run = db.find_run(Some(first.run_id())).unwrap().unwrap();
+        assert_eq!(run.stop_reason.as_deref(), Some("queue_exhausted"));
+        assert_eq!(run.elapsed_ms, 1500);
+        assert!(first.is_visited("https://a.fr/").unwrap());
+        assert_eq!(first.get_email_count().unwrap(), 1);
+
+        first.queue_url("https://a.fr/next", 2, 0).unwrap();
+        let mut seen = Vec::new();
+        first.for_each_seen_url(&mut |url, visited| seen.push((url.to_string(), visited))).unwrap();
+        assert_eq!(seen, vec![("https://a.fr/".to_string(), true), ("https://a.fr/next".to_string(), false)]);
+        assert_eq!(second.pending_count().unwrap(), 1);
+
+        assert_eq!(db.find_run(None).unwrap().unwrap().id, second.run_id());
+
+        // Only the first run's retryable failure is requeued, once
+        first.record_error("https://a.fr/", &CrawlError::Connect("refused".to_string())).unwrap();
+        first.record_error("https://a.fr/next", &CrawlError::Tls("bad certificate".to_string())).unwrap();
+        assert_eq!(first.get_error_count().unwrap(), 2);
+        assert_eq!(first.requeue_failed().unwrap(), 1);
+        assert!(!first.is_visited("https://a.fr/").unwrap());
+        assert_eq!(first.requeue_failed().unwrap(), 0);
+        assert_eq!(second.get_error_count().unwrap(), 0);
     }
 }
@@ -0,0 +1,176 @@
+use crate::storage::StorageError;
+use std::error::Error as _;
+use std::fmt;
+
+/// Why a URL could not be crawled or one of its findings processed
+#[derive(Debug)]
+pub enum CrawlError {
+    /// The host name could not be resolved
+    Dns(String),
+    /// The connection was refused or reset
+    Connect(String),
+    /// The TLS handshake or certificate check failed
+    Tls(String),
+    /// No response within `--timeout`
+    Timeout,
+    /// The server answered with a 5xx or 429 status
+    HttpStatus(u16),
+    /// The body is larger than `--max-body-size`
+    BodyTooLarge { limit: u64 },
+    /// The body could not be read or decompressed
+    Decode(String),
+    /// Any other request failure (bad URL, too many redirects, ...)
+    Request(String),
+    Db(StorageError),
+    /// The face detection script could not run
+    Detector(String),
+    /// A local file operation failed, e.g. saving an image or a WARC record
+    Io(std::io::Error),
+}
+
+impl CrawlError {
+    /// Short name used in logs, stats and the `errors` table
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CrawlError::Dns(_) => "dns",
+            CrawlError::Connect(_) => "connect",
+            CrawlError::Tls(_) => "tls",
+            CrawlError::Timeout => "timeout",
+            CrawlError::HttpStatus(_) => "http_status",
+            CrawlError::BodyTooLarge { .. } => "body_too_large",
+            CrawlError::Decode(_) => "decode",
+            CrawlError::Request(_) => "request",
+            CrawlError::Db(_) => "db",
+            CrawlError::Detector(_) => "detector",
+            CrawlError::Io(_) => "io",
+        }
+    }
+
+    /// Whether fetching again later may succeed (`resume --retry-errors`)
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CrawlError::Dns(_)
+            | CrawlError::Connect(_)
+            | CrawlError::Timeout
+            | CrawlError::Decode(_)
+            | CrawlError::Db(_)
+            | CrawlError::Io(_) => true,
+            CrawlError::HttpStatus(status) => *status >= 500 || *status == 429,
+            CrawlError::Tls(_)
+            | CrawlError::BodyTooLarge { .. }
+            | CrawlError::Request(_)
+            | CrawlError::Detector(_) => false,
+        }
+    }
+
+    /// The error for a response status, `None` unless it is a 5xx or 429
+    pub fn from_status(status: u16) -> Option<Self> {
+        (status >= 500 || status == 429).then_some(CrawlError::HttpStatus(status))
+    }
+}
+
+impl fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrawlError::Dns(e) => write!(f, "DNS lookup failed: {}", e),
+            CrawlError::Connect(e) => write!(f, "connection failed: {}", e),
+            CrawlError::Tls(e) => write!(f, "TLS error: {}", e),
+            CrawlError::Timeout => write!(f, "request timed out"),
+            CrawlError::HttpStatus(status) => write!(f, "HTTP status {}", status),
+            CrawlError::BodyTooLarge { limit } => write!(f, "body larger than {} bytes", limit),
+            CrawlError::Decode(e) => write!(f, "can't read body: {}", e),
+            CrawlError::Request(e) => write!(f, "request failed: {}", e),
+            CrawlError::Db(e) => write!(f, "storage error: {}", e),
+            CrawlError::Detector(e) => write!(f, "face detection failed: {}", e),
+            CrawlError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CrawlError {}
+
+impl From<StorageError> for CrawlError {
+    fn from(e: StorageError) -> Self {
+        CrawlError::Db(e)
+    }
+}
+
+impl From<std::io::Error> for CrawlError {
+    fn from(e: std::io::Error) -> Self {
+        CrawlError::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for CrawlError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return CrawlError::Timeout;
+        }
+        let message = error_chain(&e);
+        if e.is_connect() {
+            let lower = message.to_lowercase();
+            if ["dns error", "failed to lookup address", "name or service not known", "no such host"]
+                .iter()
+                .any(|hint| lower.contains(hint))
+            {
+                CrawlError::Dns(message)
+            } else if ["certificate", "tls", "handshake"].iter().any(|hint| lower.contains(hint)) {
+                CrawlError::Tls(message)
+            } else {
+                CrawlError::Connect(message)
+            }
+        } else if let Some(status) = e.status() {
+            CrawlError::HttpStatus(status.as_u16())
+        } else if e.is_decode() || e.is_body() {
+            CrawlError::Decode(message)
+        } else {
+            CrawlError::Request(message)
+        }
+    }
+}
+
+/// An error and its sources, the outer reqwest message alone says little
+fn error_chain(e: &reqwest::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message.push_str(": ");
+            message.push_str(&cause_message);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_and_retryable() {
+        assert_eq!(CrawlError::from_status(503).unwrap().kind(), "http_status");
+        assert!(CrawlError::from_status(429).unwrap().is_retryable());
+        assert!(CrawlError::from_status(404).is_none());
+        assert!(CrawlError::from_status(200).is_none());
+
+        assert!(CrawlError::Timeout.is_retryable());
+        assert!(!CrawlError::BodyTooLarge { limit: 10 }.is_retryable());
+        assert_eq!(CrawlError::BodyTooLarge { limit: 10 }.to_string(), "body larger than 10 bytes");
+        let db: CrawlError = StorageError::Io(std::io::Error::other("disk full")).into();
+        assert_eq!((db.kind(), db.to_string().as_str()), ("db", "storage error: disk full"));
+    }
+
+    #[tokio::test]
+    async fn test_from_reqwest() {
+        // Nothing listens on port 1
+        let e = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+        let error = CrawlError::from(e);
+        assert_eq!(error.kind(), "connect");
+        assert!(error.is_retryable());
+
+        let e = reqwest::get("not a url").await.unwrap_err();
+        assert_eq!(CrawlError::from(e).kind(), "request");
+    }
+}
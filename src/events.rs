@@ -0,0 +1,130 @@
+use futures::Stream;
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Something that happened during a crawl, see [`crate::Crawler::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CrawlEvent {
+    /// A page answered, whatever its status
+    PageFetched { url: String, status: u16, bytes: u64, depth: u32 },
+    /// A page could not be fetched (HTTP error statuses come as `PageFetched`)
+    PageFailed { url: String, kind: String, message: String, retryable: bool },
+    /// An email seen on a page, each time it is seen
+    EmailFound { email: String, url: String },
+    /// A phone number seen on a page, each time it is seen
+    PhoneFound { phone: String, url: String },
+    /// An image with a face, referenced by `page_url`, was saved under `path`
+    ImageSaved { uuid: String, url: String, page_url: String, path: String },
+    /// The workers stopped, the stream ends after this event
+    CrawlFinished { run: i64, stop_reason: String, pages: u64 },
+}
+
+impl CrawlEvent {
+    /// Emails, phones and saved images, what output sinks deliver
+    pub fn is_finding(&self) -> bool {
+        matches!(
+            self,
+            CrawlEvent::EmailFound { .. } | CrawlEvent::PhoneFound { .. } | CrawlEvent::ImageSaved { .. }
+        )
+    }
+}
+
+/// Events of a crawl, received in the order they were emitted
+///
+/// Ends once the crawl finished. Holding events back slows the crawl down
+/// instead of dropping them: workers wait for room in the subscription.
+pub struct EventStream {
+    receiver: mpsc::Receiver<CrawlEvent>,
+}
+
+impl EventStream {
+    /// The next event, `None` once the crawl finished
+    pub async fn recv(&mut self) -> Option<CrawlEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = CrawlEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<CrawlEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Subscriptions of a crawler, each a bounded channel
+#[derive(Default)]
+pub(crate) struct Events {
+    subscribers: Mutex<Vec<mpsc::Sender<CrawlEvent>>>,
+}
+
+impl Events {
+    /// A new subscription buffering up to `capacity` events
+    pub fn subscribe(&self, capacity: usize) -> EventStream {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        self.subscribers.lock().unwrap().push(sender);
+        EventStream { receiver }
+    }
+
+    /// Send an event to every subscription, waiting for room in each
+    ///
+    /// Subscriptions whose stream was dropped are removed.
+    pub async fn emit(&self, event: CrawlEvent) {
+        let subscribers = self.subscribers.lock().unwrap().clone();
+        for subscriber in subscribers {
+            if subscriber.send(event.clone()).await.is_err() {
+                self.subscribers.lock().unwrap().retain(|s| !s.same_channel(&subscriber));
+            }
+        }
+    }
+
+    /// End every subscription's stream
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    fn email(n: usize) -> CrawlEvent {
+        CrawlEvent::EmailFound { email: format!("user{}@a.fr", n), url: "https://a.fr/".to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_and_close() {
+        let events = Arc::new(Events::default());
+        let mut stream = events.subscribe(1);
+        let dropped = events.subscribe(1);
+        drop(dropped);
+
+        // The emitter waits for the slow subscriber instead of losing events
+        let emitter = events.clone();
+        let task = tokio::spawn(async move {
+            for n in 0..5 {
+                emitter.emit(email(n)).await;
+            }
+            emitter.close();
+        });
+        let mut received = Vec::new();
+        while let Some(event) = stream.next().await {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            received.push(event);
+        }
+        task.await.unwrap();
+
+        assert_eq!(received, (0..5).map(email).collect::<Vec<_>>());
+        assert!(events.subscribers.lock().unwrap().is_empty());
+
+        let json = serde_json::to_value(email(0)).unwrap();
+        assert_eq!(json["event"], "email_found");
+        assert_eq!(json["email"], "user0@a.fr");
+    }
+}
@@ -0,0 +1,427 @@
+use crate::extractor::is_same_domain;
+use clap::ValueEnum;
+use rusqlite::{named_params, Connection, Row};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use url::Url;
+
+/// Table to export
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportKind {
+    /// One row per email seen on a page
+    Emails,
+    /// One row per phone number seen on a page
+    Phones,
+    /// One row per image with a face
+    Images,
+    /// One row per fetched page
+    Pages,
+}
+
+/// Output format
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    Csv,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// Restricts the exported rows, unset fields match everything
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    pub run: Option<i64>,
+    /// Seed URL of the run
+    pub seed: Option<String>,
+    /// Host of the page, subdomains included
+    pub host: Option<String>,
+    pub email_domain: Option<String>,
+    /// Seen on or after this date (`YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`, UTC)
+    pub since: Option<String>,
+    /// Seen on or before this date
+    pub until: Option<String>,
+    pub min_confidence: Option<f64>,
+}
+
+impl ExportKind {
+    /// Columns in default order
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            ExportKind::Emails => &[
+                "email", "domain", "page_url", "host", "run_id", "seed", "first_seen", "last_seen", "occurrences",
+                "url_count", "confidence",
+            ],
+            ExportKind::Phones => &[
+                "phone", "page_url", "host", "run_id", "seed", "first_seen", "last_seen", "occurrences", "url_count",
+                "confidence",
+            ],
+            ExportKind::Images => &["uuid", "page_url", "image_url", "host", "run_id", "seed", "found_at"],
+            ExportKind::Pages => &["url", "host", "run_id", "seed", "fetched_at", "duplicate_of", "stored"],
+        }
+    }
+
+    /// Query returning the columns read from the database, filtered by the
+    /// `:run`, `:seed`, `:since`, `:until` and `:email_domain` parameters
+    fn query(self) -> &'static str {
+        match self {
+            ExportKind::Emails => {
+                "SELECT e.email, p.url, p.run_id, json_extract(r.seeds, '$[0]'), s.first_seen, s.last_seen,
+                     e.occurrences, e.url_count
+                 FROM email_sightings s
+                 JOIN emails e ON e.id = s.email_id
+                 JOIN pages p ON p.id = s.page_id
+                 LEFT JOIN runs r ON r.id = p.run_id
+                 WHERE (:run IS NULL OR p.run_id = :run)
+                   AND (:seed IS NULL OR EXISTS (SELECT 1 FROM json_each(r.seeds) WHERE value = :seed))
+                   AND (:since IS NULL OR s.last_seen >= :since)
+                   AND (:until IS NULL OR s.first_seen <= :until)
+                   AND (:email_domain IS NULL OR e.email LIKE '%@' || :email_domain)
+                 ORDER BY s.id"
+            }
+            ExportKind::Phones => {
+                "SELECT ph.phone, p.url, p.run_id, json_extract(r.seeds, '$[0]'), s.first_seen, s.last_seen,
+                     ph.occurrences, ph.url_count
+                 FROM phone_sightings s
+                 JOIN phones ph ON ph.id = s.phone_id
+                 JOIN pages p ON p.id = s.page_id
+                 LEFT JOIN runs r ON r.id = p.run_id
+                 WHERE (:run IS NULL OR p.run_id = :run)
+                   AND (:seed IS NULL OR EXISTS (SELECT 1 FROM json_each(r.seeds) WHERE value = :seed))
+                   AND (:since IS NULL OR s.last_seen >= :since)
+                   AND (:until IS NULL OR s.first_seen <= :until)
+                   AND :email_domain IS NULL
+                 ORDER BY s.id"
+            }
+            ExportKind::Images => {
+                "SELECT i.uuid, p.url, p.run_id, json_extract(r.seeds, '$[0]'), i.found_at, i.image_url
+                 FROM images i
+                 JOIN pages p ON p.id = i.page_id
+                 LEFT JOIN runs r ON r.id = p.run_id
+                 WHERE (:run IS NULL OR p.run_id = :run)
+                   AND (:seed IS NULL OR EXISTS (SELECT 1 FROM json_each(r.seeds) WHERE value = :seed))
+                   AND (:since IS NULL OR i.found_at >= :since)
+                   AND (:until IS NULL OR i.found_at <= :until)
+                   AND :email_domain IS NULL
+                 ORDER BY i.id"
+            }
+            ExportKind::Pages => {
+                "SELECT p.url, p.run_id, json_extract(r.seeds, '$[0]'), p.fetched_at, d.url,
+                     EXISTS (SELECT 1 FROM page_contents c WHERE c.page_id = p.id)
+                 FROM pages p
+                 LEFT JOIN pages d ON d.id = p.duplicate_of
+                 LEFT JOIN runs r ON r.id = p.run_id
+                 WHERE (:run IS NULL OR p.run_id = :run)
+                   AND (:seed IS NULL OR EXISTS (SELECT 1 FROM json_each(r.seeds) WHERE value = :seed))
+                   AND (:since IS NULL OR p.fetched_at >= :since)
+                   AND (:until IS NULL OR p.fetched_at <= :until)
+                   AND :email_domain IS NULL
+                 ORDER BY p.id"
+            }
+        }
+    }
+
+    /// Build a record in `columns()` order from a row of `query()`
+    fn record(self, row: &Row) -> rusqlite::Result<Vec<Value>> {
+        let text = |i: usize| -> rusqlite::Result<Value> {
+            Ok(row.get::<_, Option<String>>(i)?.map_or(Value::Null, Value::from))
+        };
+        let int = |i: usize| -> rusqlite::Result<Value> {
+            Ok(row.get::<_, Option<i64>>(i)?.map_or(Value::Null, Value::from))
+        };
+
+        Ok(match self {
+            ExportKind::Emails => {
+                let email: String = row.get(0)?;
+                let page_url: String = row.get(1)?;
+                let url_count: u64 = row.get(7)?;
+                let domain = email.rsplit('@').next().unwrap_or_default().to_string();
+                let confidence = confidence(url_count, Some(&domain), &page_url);
+                let host = host_of(&page_url);
+                vec![
+                    Value::from(email),
+                    Value::from(domain),
+                    Value::from(page_url),
+                    host,
+                    int(2)?,
+                    text(3)?,
+                    text(4)?,
+                    text(5)?,
+                    int(6)?,
+                    Value::from(url_count),
+                    Value::from(confidence),
+                ]
+            }
+            ExportKind::Phones => {
+                let page_url: String = row.get(1)?;
+                let url_count: u64 = row.get(7)?;
+                let confidence = confidence(url_count, None, &page_url);
+                let host = host_of(&page_url);
+                vec![
+                    text(0)?,
+                    Value::from(page_url),
+                    host,
+                    int(2)?,
+                    text(3)?,
+                    text(4)?,
+                    text(5)?,
+                    int(6)?,
+                    Value::from(url_count),
+                    Value::from(confidence),
+                ]
+            }
+            ExportKind::Images => {
+                let page_url: String = row.get(1)?;
+                let host = host_of(&page_url);
+                vec![text(0)?, Value::from(page_url), text(5)?, host, int(2)?, text(3)?, text(4)?]
+            }
+            ExportKind::Pages => {
+                let url: String = row.get(0)?;
+                let host = host_of(&url);
+                vec![
+                    Value::from(url),
+                    host,
+                    int(1)?,
+                    text(2)?,
+                    text(3)?,
+                    text(4)?,
+                    Value::from(row.get::<_, bool>(5)?),
+                ]
+            }
+        })
+    }
+}
+
+/// How likely a finding is genuine, between 0.5 and 1
+///
+/// Each distinct page the value was seen on corroborates it, as does an email
+/// whose domain matches the page's host. Every corroboration halves the doubt.
+fn confidence(url_count: u64, email_domain: Option<&str>, page_url: &str) -> f64 {
+    let domain_matches = match (email_domain, Url::parse(page_url)) {
+        (Some(domain), Ok(url)) => is_same_domain(&url, domain),
+        _ => false,
+    };
+    let corroborations = url_count.max(1) + domain_matches as u64;
+    let confidence = 1.0 - 0.5f64.powi(corroborations.min(30) as i32);
+    (confidence * 100.0).round() / 100.0
+}
+
+fn host_of(url: &str) -> Value {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .map_or(Value::Null, Value::from)
+}
+
+/// Stream the rows of `kind` matching `filter` to `out`, returns how many were written
+///
+/// `columns` selects and orders the columns, all of them when empty.
+pub fn export(
+    conn: &Connection,
+    kind: ExportKind,
+    format: ExportFormat,
+    filter: &ExportFilter,
+    columns: &[String],
+    out: &mut dyn Write,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let available = kind.columns();
+    let selected: Vec<usize> = if columns.is_empty() {
+        (0..available.len()).collect()
+    } else {
+        columns
+            .iter()
+            .map(|column| {
+                available.iter().position(|c| c == column).ok_or_else(|| {
+                    format!("Unknown column '{}' for {:?}, expected one of: {}", column, kind, available.join(", "))
+                })
+            })
+            .collect::<Result<_, _>>()?
+    };
+    if filter.email_domain.is_some() && kind != ExportKind::Emails {
+        return Err("--email-domain only applies to emails".into());
+    }
+    if filter.min_confidence.is_some() && !matches!(kind, ExportKind::Emails | ExportKind::Phones) {
+        return Err("--min-confidence only applies to emails and phones".into());
+    }
+    let confidence_column = available.iter().position(|c| *c == "confidence");
+    let host_column = available.iter().position(|c| *c == "host");
+
+    let mut stmt = conn.prepare(kind.query())?;
+    let mut rows = stmt.query(named_params! {
+        ":run": filter.run,
+        ":seed": filter.seed,
+        ":since": filter.since,
+        ":until": filter.until.as_deref().map(end_of_day),
+        ":email_domain": filter.email_domain,
+    })?;
+
+    let names: Vec<&str> = selected.iter().map(|&i| available[i]).collect();
+    let mut writer = RecordWriter::new(format, &names, out)?;
+    while let Some(row) = rows.next()? {
+        let record = kind.record(row)?;
+
+        if let (Some(host), Some(column)) = (&filter.host, host_column) {
+            let matches = record[column]
+                .as_str()
+                .and_then(|page_host| Url::parse(&format!("http://{}/", page_host)).ok())
+                .is_some_and(|url| is_same_domain(&url, host));
+            if !matches {
+                continue;
+            }
+        }
+        if let (Some(min), Some(column)) = (filter.min_confidence, confidence_column) {
+            if record[column].as_f64().is_none_or(|confidence| confidence < min) {
+                continue;
+            }
+        }
+
+        let values: Vec<&Value> = selected.iter().map(|&i| &record[i]).collect();
+        writer.write(&values)?;
+    }
+    writer.finish()
+}
+
+/// A bare date as an upper bound covers the whole day
+fn end_of_day(date: &str) -> String {
+    if date.len() == 10 {
+        format!("{} 23:59:59", date)
+    } else {
+        date.to_string()
+    }
+}
+
+/// Writes records one at a time in the chosen format
+struct RecordWriter<'a> {
+    format: ExportFormat,
+    columns: Vec<String>,
+    out: &'a mut dyn Write,
+    count: u64,
+}
+
+impl<'a> RecordWriter<'a> {
+    fn new(format: ExportFormat, columns: &[&str], out: &'a mut dyn Write) -> std::io::Result<Self> {
+        match format {
+            ExportFormat::Csv => {
+                let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+                writeln!(out, "{}", header.join(","))?;
+            }
+            ExportFormat::Json => write!(out, "[")?,
+            ExportFormat::Ndjson => {}
+        }
+        let columns = columns.iter().map(|c| serde_json::to_string(c).unwrap()).collect();
+        Ok(RecordWriter { format, columns, out, count: 0 })
+    }
+
+    fn write(&mut self, values: &[&Value]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.format {
+            ExportFormat::Csv => {
+                let fields: Vec<String> = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => String::new(),
+                        Value::String(s) => csv_field(s),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                writeln!(self.out, "{}", fields.join(","))?;
+            }
+            ExportFormat::Json | ExportFormat::Ndjson => {
+                if self.format == ExportFormat::Json {
+                    write!(self.out, "{}\n  ", if self.count == 0 { "" } else { "," })?;
+                }
+                write!(self.out, "{{")?;
+                for (i, (column, value)) in self.columns.iter().zip(values).enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(self.out, "{}{}:{}", separator, column, serde_json::to_string(value)?)?;
+                }
+                write!(self.out, "}}")?;
+                if self.format == ExportFormat::Ndjson {
+                    writeln!(self.out)?;
+                }
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<u64, Box<dyn std::error::Error>> {
+        if self.format == ExportFormat::Json {
+            writeln!(self.out, "{}]", if self.count == 0 { "" } else { "\n" })?;
+        }
+        self.out.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, TempDb};
+
+    fn run_export(
+        conn: &Connection,
+        kind: ExportKind,
+        format: ExportFormat,
+        filter: &ExportFilter,
+        columns: &[&str],
+    ) -> String {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        let mut out = Vec::new();
+        export(conn, kind, format, filter, &columns, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_export_formats_and_filters() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let first = db.for_run(db.begin_run("[\"https://a.fr/\"]", "{}").unwrap());
+        first.insert_emails(vec!["x@a.fr".to_string(), "y@b.fr".to_string()], "https://a.fr/").unwrap();
+        first.insert_emails(vec!["x@a.fr".to_string()], "https://blog.a.fr/team").unwrap();
+        let second = db.for_run(db.begin_run("[\"https://c.fr/\"]", "{}").unwrap());
+        second.insert_emails(vec!["z@c.fr, \"sales\"".to_string()], "https://c.fr/").unwrap();
+        drop((db, first, second));
+
+        let conn = Database::open_read_only(temp.path()).unwrap();
+        let all = ExportFilter::default();
+
+        let csv = run_export(&conn, ExportKind::Emails, ExportFormat::Csv, &all, &["email", "host", "confidence"]);
+        assert_eq!(
+            csv,
+            "email,host,confidence\nx@a.fr,a.fr,0.88\ny@b.fr,a.fr,0.5\nx@a.fr,blog.a.fr,0.88\n\"z@c.fr, \"\"sales\"\"\",c.fr,0.5\n"
+        );
+
+        let filter = ExportFilter { seed: Some("https://a.fr/".to_string()), min_confidence: Some(0.8), ..Default::default() };
+        let ndjson = run_export(&conn, ExportKind::Emails, ExportFormat::Ndjson, &filter, &["page_url", "email"]);
+        assert_eq!(
+            ndjson,
+            "{\"page_url\":\"https://a.fr/\",\"email\":\"x@a.fr\"}\n{\"page_url\":\"https://blog.a.fr/team\",\"email\":\"x@a.fr\"}\n"
+        );
+
+        let filter = ExportFilter { host: Some("blog.a.fr".to_string()), ..Default::default() };
+        let json = run_export(&conn, ExportKind::Pages, ExportFormat::Json, &filter, &["url", "stored"]);
+        let pages: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(pages, serde_json::json!([{ "url": "https://blog.a.fr/team", "stored": false }]));
+
+        let filter = ExportFilter { run: Some(1), email_domain: Some("b.fr".to_string()), ..Default::default() };
+        assert_eq!(run_export(&conn, ExportKind::Emails, ExportFormat::Json, &filter, &["run_id"]), "[\n  {\"run_id\":1}\n]\n");
+        let filter = ExportFilter { until: Some("2000-01-01".to_string()), ..Default::default() };
+        assert_eq!(run_export(&conn, ExportKind::Emails, ExportFormat::Json, &filter, &[]), "[]\n");
+
+        let mut out = Vec::new();
+        let unknown = export(&conn, ExportKind::Images, ExportFormat::Csv, &all, &["email".to_string()], &mut out);
+        assert!(unknown.is_err());
+    }
+}
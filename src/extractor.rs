@@ -1,7 +1,8 @@
 use regex::Regex;
 use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
 use url::Url;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 lazy_static::lazy_static! {
     static ref EMAIL_REGEX: Regex = Regex::new(
@@ -21,6 +22,49 @@ lazy_static::lazy_static! {
     ).unwrap();
 }
 
+/// Which findings a crawl extracts (config file `[extractor]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractorConfig {
+    pub emails: bool,
+    pub phones: bool,
+    /// Emails at these domains or their subdomains are dropped
+    pub ignore_email_domains: Vec<String>,
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        ExtractorConfig { emails: true, phones: true, ignore_email_domains: Vec::new() }
+    }
+}
+
+impl ExtractorConfig {
+    /// Whether an extracted (lowercase) email is kept
+    pub fn keeps_email(&self, email: &str) -> bool {
+        let domain = email.rsplit('@').next().unwrap_or_default();
+        !self.ignore_email_domains.iter().any(|ignored| {
+            let ignored = ignored.to_lowercase();
+            domain == ignored || domain.ends_with(&format!(".{}", ignored))
+        })
+    }
+
+    /// Emails of a page this configuration keeps
+    pub fn emails_in(&self, html: &str) -> Vec<String> {
+        if !self.emails {
+            return Vec::new();
+        }
+        extract_emails(html).into_iter().filter(|email| self.keeps_email(email)).collect()
+    }
+
+    /// Phone numbers of a page, if this configuration extracts them
+    pub fn phones_in(&self, html: &str) -> Vec<String> {
+        if !self.phones {
+            return Vec::new();
+        }
+        extract_phones(html)
+    }
+}
+
 /// Extract all email addresses from HTML content
 pub fn extract_emails(html: &str) -> Vec<String> {
     let mut emails: HashSet<String> = HashSet::new();
@@ -78,8 +122,8 @@ fn normalize_phone(phone: &str) -> String {
         .collect();
     
     // Convert +33 to 0 for consistency
-    if digits.starts_with("+33") {
-        format!("0{}", &digits[3..])
+    if let Some(rest) = digits.strip_prefix("+33") {
+        format!("0{}", rest)
     } else if digits.starts_with("33") && digits.len() == 11 {
         format!("0{}", &digits[2..])
     } else {
@@ -87,11 +131,19 @@ fn normalize_phone(phone: &str) -> String {
     }
 }
 
-/// Extract all links from HTML content
-pub fn extract_links(html: &str, base_url: &Url) -> Vec<Url> {
+/// A link found on a page
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub url: Url,
+    /// Anchor text, whitespace collapsed
+    pub text: String,
+}
+
+/// Extract all links from HTML content, skipping `rel="nofollow"` anchors if requested
+pub fn extract_links(html: &str, base_url: &Url, honor_nofollow: bool) -> Vec<Link> {
     let document = Html::parse_document(html);
     let selector = Selector::parse("a[href]").unwrap();
-    let mut links: HashSet<Url> = HashSet::new();
+    let mut links: HashMap<Url, String> = HashMap::new();
     
     for element in document.select(&selector) {
         if let Some(href) = element.value().attr("href") {
@@ -104,6 +156,11 @@ pub fn extract_links(html: &str, base_url: &Url) -> Vec<Url> {
             {
                 continue;
             }
+
+            // Skip links the page asks crawlers not to follow
+            if honor_nofollow && has_nofollow(element.value().attr("rel")) {
+                continue;
+            }
             
             // Try to resolve the URL
             if let Ok(resolved) = base_url.join(href) {
@@ -112,13 +169,29 @@ pub fn extract_links(html: &str, base_url: &Url) -> Vec<Url> {
                     // Remove fragment
                     let mut clean_url = resolved.clone();
                     clean_url.set_fragment(None);
-                    links.insert(clean_url);
+
+                    // Keep every anchor text pointing to the same URL
+                    let text = element.text().collect::<Vec<_>>().join(" ");
+                    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    let entry = links.entry(clean_url).or_default();
+                    if !text.is_empty() {
+                        if !entry.is_empty() {
+                            entry.push(' ');
+                        }
+                        entry.push_str(&text);
+                    }
                 }
             }
         }
     }
     
-    links.into_iter().collect()
+    links.into_iter().map(|(url, text)| Link { url, text }).collect()
+}
+
+/// Check if a `rel` attribute value contains `nofollow`
+fn has_nofollow(rel: Option<&str>) -> bool {
+    rel.map(|rel| rel.split_whitespace().any(|token| token.eq_ignore_ascii_case("nofollow")))
+        .unwrap_or(false)
 }
 
 /// Check if a URL belongs to the same domain as the base
@@ -162,11 +235,32 @@ mod tests {
         "#;
         
         let base = Url::parse("https://example.com").unwrap();
-        let links = extract_links(html, &base);
+        let links = extract_links(html, &base, true);
         
-        assert!(links.iter().any(|u| u.path() == "/page1"));
-        assert!(links.iter().any(|u| u.path() == "/page2"));
-        assert!(!links.iter().any(|u| u.scheme() == "mailto"));
+        assert!(links.iter().any(|l| l.url.path() == "/page1" && l.text == "Page 1"));
+        assert!(links.iter().any(|l| l.url.path() == "/page2"));
+        assert!(!links.iter().any(|l| l.url.scheme() == "mailto"));
+    }
+
+    #[test]
+    fn test_extract_links_nofollow() {
+        let html = r#"
+            <html>
+                <body>
+                    <a href="/public">Public</a>
+                    <a href="/login" rel="noopener NoFollow">Login</a>
+                </body>
+            </html>
+        "#;
+
+        let base = Url::parse("https://example.com").unwrap();
+
+        let links = extract_links(html, &base, true);
+        assert!(links.iter().any(|l| l.url.path() == "/public"));
+        assert!(!links.iter().any(|l| l.url.path() == "/login"));
+
+        let links = extract_links(html, &base, false);
+        assert!(links.iter().any(|l| l.url.path() == "/login"));
     }
 
     #[test]
@@ -198,4 +292,22 @@ mod tests {
         assert_eq!(normalize_phone("01.02.03.04.05"), "0102030405");
         assert_eq!(normalize_phone("01-02-03-04-05"), "0102030405");
     }
+
+    #[test]
+    fn test_ignore_email_domains() {
+        let config = ExtractorConfig { ignore_email_domains: vec!["Example.com".to_string()], ..Default::default() };
+        assert!(!config.keeps_email("info@example.com"));
+        assert!(!config.keeps_email("info@mail.example.com"));
+        assert!(config.keeps_email("info@notexample.com"));
+        assert!(ExtractorConfig::default().keeps_email("info@example.com"));
+    }
+
+    #[test]
+    fn test_config_extraction() {
+        let html = "<p>info@example.com, sales@shop.fr, +33 1 23 45 67 89</p>";
+        let config = ExtractorConfig { phones: false, ignore_email_domains: vec!["example.com".to_string()], ..Default::default() };
+        assert_eq!(config.emails_in(html), vec!["sales@shop.fr".to_string()]);
+        assert!(config.phones_in(html).is_empty());
+        assert_eq!(ExtractorConfig::default().phones_in(html), extract_phones(html));
+    }
 }
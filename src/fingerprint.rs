@@ -0,0 +1,226 @@
+use scraper::{Html, Node};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Number of words per SimHash shingle
+const SHINGLE_SIZE: usize = 3;
+
+/// Content fingerprint of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub exact: u64,
+    pub simhash: u64,
+}
+
+impl Fingerprint {
+    /// Fingerprint the visible text of an HTML document (None if it has no text)
+    pub fn of_html(html: &str) -> Option<Self> {
+        let text = visible_text(html);
+        if text.is_empty() {
+            return None;
+        }
+        Some(Fingerprint {
+            exact: fnv1a(text.as_bytes()),
+            simhash: simhash(&text),
+        })
+    }
+}
+
+/// Extract the visible text of an HTML document, lowercased with collapsed whitespace
+pub fn visible_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let mut words: Vec<String> = Vec::new();
+
+    for node in document.tree.nodes() {
+        let text = match node.value() {
+            Node::Text(text) => text,
+            _ => continue,
+        };
+
+        // Skip text that is never rendered
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor.value().as_element().is_some_and(|el| {
+                matches!(el.name(), "script" | "style" | "noscript" | "template" | "head")
+            })
+        });
+        if hidden {
+            continue;
+        }
+
+        words.extend(text.split_whitespace().map(|w| w.to_lowercase()));
+    }
+
+    words.join(" ")
+}
+
+/// 64-bit FNV-1a hash (stable across builds, unlike `DefaultHasher`)
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Compute the SimHash of a text over word shingles
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return 0;
+    }
+
+    let mut weights = [0i64; 64];
+    let shingle_count = words.len().saturating_sub(SHINGLE_SIZE - 1).max(1);
+    for i in 0..shingle_count {
+        let end = (i + SHINGLE_SIZE).min(words.len());
+        let hash = fnv1a(words[i..end].join(" ").as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0u64, |acc, (bit, _)| acc | (1 << bit))
+}
+
+/// Number of differing bits between two SimHashes
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[derive(Default)]
+struct IndexState {
+    exact: HashMap<u64, String>,
+    // (band index, band value) -> (simhash, url)
+    bands: HashMap<(usize, u64), Vec<(u64, String)>>,
+}
+
+/// In-memory index of page fingerprints for near-duplicate lookups
+///
+/// The SimHash is split into `max_distance + 1` bands: two hashes within
+/// `max_distance` bits must agree exactly on at least one band, so only
+/// pages sharing a band are compared.
+pub struct FingerprintIndex {
+    max_distance: u32,
+    state: Mutex<IndexState>,
+}
+
+impl FingerprintIndex {
+    /// Create an index matching pages within `max_distance` SimHash bits
+    pub fn new(max_distance: u32) -> Self {
+        FingerprintIndex {
+            max_distance: max_distance.min(63),
+            state: Mutex::new(IndexState::default()),
+        }
+    }
+
+    /// Find a previously indexed page matching this fingerprint, then index it
+    pub fn check_and_insert(&self, url: &str, fingerprint: Fingerprint) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+
+        let duplicate_of = state.exact.get(&fingerprint.exact).cloned().or_else(|| {
+            self.band_keys(fingerprint.simhash)
+                .iter()
+                .filter_map(|key| state.bands.get(key))
+                .flatten()
+                .find(|(other, _)| hamming_distance(*other, fingerprint.simhash) <= self.max_distance)
+                .map(|(_, other_url)| other_url.clone())
+        });
+
+        // Only originals are indexed, duplicates point back to them
+        if duplicate_of.is_none() {
+            self.insert_locked(&mut state, url, fingerprint);
+        }
+
+        duplicate_of
+    }
+
+    /// Index a page without looking it up (used when warming from the database)
+    pub fn insert(&self, url: &str, fingerprint: Fingerprint) {
+        let mut state = self.state.lock().unwrap();
+        self.insert_locked(&mut state, url, fingerprint);
+    }
+
+    fn insert_locked(&self, state: &mut IndexState, url: &str, fingerprint: Fingerprint) {
+        state.exact.entry(fingerprint.exact).or_insert_with(|| url.to_string());
+        for key in self.band_keys(fingerprint.simhash) {
+            state
+                .bands
+                .entry(key)
+                .or_default()
+                .push((fingerprint.simhash, url.to_string()));
+        }
+    }
+
+    fn band_keys(&self, simhash: u64) -> Vec<(usize, u64)> {
+        let bands = self.max_distance as usize + 1;
+        let width = 64 / bands;
+        (0..bands)
+            .map(|band| {
+                let shift = band * width;
+                // The last band takes the remaining bits
+                let bits = if band == bands - 1 { 64 - shift } else { width };
+                let mask = if bits == 64 { u64::MAX } else { (1u64 << bits) - 1 };
+                (band, (simhash >> shift) & mask)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_text() {
+        let html = r#"
+            <html>
+                <head><title>Ignored</title><style>body { color: red; }</style></head>
+                <body>
+                    <h1>Hello   World</h1>
+                    <script>var x = "hidden";</script>
+                    <p>Contact us</p>
+                </body>
+            </html>
+        "#;
+
+        assert_eq!(visible_text(html), "hello world contact us");
+    }
+
+    #[test]
+    fn test_simhash_similarity() {
+        let base = "the quick brown fox jumps over the lazy dog near the river bank on a sunny afternoon in june";
+        let tweaked = "the quick brown fox jumps over the lazy dog near the river bank on a sunny afternoon in july";
+        let different = "annual report of the board of directors regarding fiscal policy and quarterly earnings";
+
+        let near = hamming_distance(simhash(base), simhash(tweaked));
+        let far = hamming_distance(simhash(base), simhash(different));
+        assert!(near < far, "near={} far={}", near, far);
+        assert_eq!(simhash(base), simhash(base));
+    }
+
+    #[test]
+    fn test_index_exact_and_near_duplicates() {
+        let index = FingerprintIndex::new(3);
+
+        let original = Fingerprint { exact: 1, simhash: 0xFFFF_0000_FFFF_0000 };
+        assert_eq!(index.check_and_insert("https://example.com/a", original), None);
+
+        let copy = Fingerprint { exact: 1, simhash: 0 };
+        assert_eq!(index.check_and_insert("https://example.com/a?print=1", copy).as_deref(), Some("https://example.com/a"));
+
+        let near = Fingerprint { exact: 2, simhash: 0xFFFF_0000_FFFF_0007 };
+        assert_eq!(index.check_and_insert("https://example.com/a?sid=42", near).as_deref(), Some("https://example.com/a"));
+
+        let far = Fingerprint { exact: 3, simhash: 0x0000_FFFF_0000_FFFF };
+        assert_eq!(index.check_and_insert("https://example.com/b", far), None);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::database::Database;
-use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::error::CrawlError;
+use crate::events::{CrawlEvent, Events};
+use crate::metrics::IMAGE_DETECTIONS;
+use crate::storage::{self, Storage};
 use reqwest::Client;
 use scraper::{Html, Selector};
 use std::collections::HashSet;
@@ -9,6 +10,7 @@ use std::process::Command;
 use std::sync::Arc;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
 use url::Url;
 use uuid::Uuid;
 
@@ -68,25 +70,25 @@ impl ImageProcessor {
         images.into_iter().collect()
     }
 
-    /// Download an image with progress bar and return the local path
-    async fn download_image(&self, client: &Client, url: &Url) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    /// Download an image and return the local path, `None` if the URL is not an image
+    async fn download_image(&self, client: &Client, url: &Url) -> Result<Option<PathBuf>, CrawlError> {
         let response = client
             .get(url.as_str())
             .send()
             .await?;
+        if let Some(e) = CrawlError::from_status(response.status().as_u16()) {
+            return Err(e);
+        }
 
         // Check if it's an image
         if let Some(content_type) = response.headers().get("content-type") {
             if let Ok(ct) = content_type.to_str() {
                 if !ct.contains("image/") {
-                    return Err("Not an image".into());
+                    return Ok(None);
                 }
             }
         }
 
-        // Get content length for progress bar
-        let total_size = response.content_length().unwrap_or(0);
-
         // Create temp directory if needed
         let temp_dir = self.output_dir.join("temp");
         fs::create_dir_all(&temp_dir).await?;
@@ -109,35 +111,18 @@ impl ImageProcessor {
         // Create file
         let mut file = fs::File::create(&file_path).await?;
 
-        // Download with progress bar
-        if total_size > 0 {
-            let pb = ProgressBar::new(total_size);
-            pb.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"));
-
-            let mut downloaded: u64 = 0;
-            let mut stream = response.bytes().await?;
-            
-            file.write_all(&stream).await?;
-            downloaded += stream.len() as u64;
-            pb.set_position(downloaded);
-            pb.finish_with_message("Downloaded");
-        } else {
-            // No content length, just download
-            let bytes = response.bytes().await?;
-            file.write_all(&bytes).await?;
-        }
+        // Download, progress is shown by the crawl's status display
+        let bytes = response.bytes().await?;
+        file.write_all(&bytes).await?;
 
         file.flush().await?;
         drop(file);
 
-        Ok(file_path)
+        Ok(Some(file_path))
     }
 
     /// Check if image is large enough by reading its header
-    async fn check_image_size(&self, path: &Path) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    async fn check_image_size(&self, path: &Path) -> Result<bool, CrawlError> {
         let bytes = fs::read(path).await?;
         
         // Try to get image dimensions from header
@@ -172,7 +157,7 @@ impl ImageProcessor {
                 }
                 let marker = bytes[i + 1];
                 // SOF0, SOF1, SOF2 markers
-                if marker >= 0xC0 && marker <= 0xC3 {
+                if (0xC0..=0xC3).contains(&marker) {
                     let height = u16::from_be_bytes([bytes[i + 5], bytes[i + 6]]) as u32;
                     let width = u16::from_be_bytes([bytes[i + 7], bytes[i + 8]]) as u32;
                     return Some((width, height));
@@ -210,16 +195,13 @@ impl ImageProcessor {
         None
     }
 
-    /// Detect faces using YOLOv12 model via Python script
-    fn detect_face(&self, image_path: &Path, verbose: bool) -> bool {
+    /// Detect faces using YOLOv12 model via Python script, fails if the script can't run
+    fn detect_face(&self, image_path: &Path) -> Result<bool, CrawlError> {
         // Get the directory where the model is located for the script
         let script_dir = self.model_path.parent().unwrap_or(Path::new("."));
         let script_path = script_dir.join("face_detect.py");
 
-        if verbose {
-            println!("{}", format!("[YOLO] Running face detection on {:?}...", image_path.file_name().unwrap()).blue());
-            println!("{}", format!("[YOLO] Using 'py -3' with script: {:?}", script_path).blue());
-        }
+        debug!(image = ?image_path.file_name().unwrap(), script = ?script_path, "Running face detection with 'py -3'");
 
         // Run Python script - use 'py -3' to ensure Python 3.x on Windows
         let output = Command::new("py")
@@ -231,63 +213,72 @@ impl ImageProcessor {
 
         match output {
             Ok(result) => {
-                if verbose {
-                    if let Ok(stdout) = String::from_utf8(result.stdout.clone()) {
-                        if !stdout.is_empty() {
-                            println!("{}", format!("[YOLO] {}", stdout.trim()).blue());
-                        }
+                if let Ok(stdout) = String::from_utf8(result.stdout.clone()) {
+                    if !stdout.is_empty() {
+                        debug!(output = stdout.trim(), "Face detection output");
                     }
-                    if !result.status.success() {
-                        if let Ok(stderr) = String::from_utf8(result.stderr) {
-                            if !stderr.is_empty() {
-                                eprintln!("{}", format!("[YOLO Error] {}", stderr.trim()).red());
-                            }
+                }
+                if !result.status.success() {
+                    if let Ok(stderr) = String::from_utf8(result.stderr) {
+                        if !stderr.is_empty() {
+                            warn!(error = stderr.trim(), "Face detection failed");
                         }
                     }
                 }
-                result.status.success()
-            },
-            Err(e) => {
-                if verbose {
-                    eprintln!("{}", format!("[YOLO Error] Failed to run python3: {}", e).red());
-                }
-                false
+                Ok(result.status.success())
             },
+            Err(e) => Err(CrawlError::Detector(format!("can't run {}: {}", script_path.display(), e))),
         }
     }
 
-    /// Process an image: download, check size, detect face, save or delete
+    /// Process an image referenced by `page_url`: download, check size, detect face, save or delete
     pub async fn process_image(
         &self,
         client: &Client,
         url: &Url,
-        db: &Arc<Database>,
-        verbose: bool,
-    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        page_url: &str,
+        db: &Arc<dyn Storage>,
+        events: &Events,
+    ) -> Result<bool, CrawlError> {
         // Download the image
         let temp_path = match self.download_image(client, url).await {
-            Ok(path) => path,
-            Err(e) => {
-                if verbose {
-                    eprintln!("{}", format!("[Image Error] {}: {}", url, e).red());
-                }
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                debug!(image = %url, "Not an image, skipping");
+                IMAGE_DETECTIONS.with_label_values(&["not_image"]).inc();
                 return Ok(false);
             }
+            Err(e) => {
+                IMAGE_DETECTIONS.with_label_values(&["download_error"]).inc();
+                return Err(e);
+            }
         };
 
         // Check image size
-        let is_large_enough = self.check_image_size(&temp_path).await.unwrap_or(false);
+        let is_large_enough = match self.check_image_size(&temp_path).await {
+            Ok(is_large_enough) => is_large_enough,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                return Err(e);
+            }
+        };
         if !is_large_enough {
             // Delete too small image
             let _ = fs::remove_file(&temp_path).await;
-            if verbose {
-                println!("{}", format!("[Image] Too small, skipping: {}", url).yellow());
-            }
+            debug!(image = %url, "Image too small, skipping");
+            IMAGE_DETECTIONS.with_label_values(&["too_small"]).inc();
             return Ok(false);
         }
 
         // Detect face
-        let has_face = self.detect_face(&temp_path, verbose);
+        let has_face = match self.detect_face(&temp_path) {
+            Ok(has_face) => has_face,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                return Err(e);
+            }
+        };
+        IMAGE_DETECTIONS.with_label_values(&[if has_face { "face" } else { "no_face" }]).inc();
 
         if has_face {
             // Extract UUID from filename
@@ -305,16 +296,22 @@ impl ImageProcessor {
             fs::rename(&temp_path, &final_path).await?;
 
             // Insert into database
-            let _ = db.insert_image(&uuid, url.as_str());
-
-            println!("{}", format!("[Face Found] Saved {} from {}", uuid, url).green());
+            let (image_uuid, page, image) = (uuid.clone(), page_url.to_string(), url.to_string());
+            storage::call(db, move |db| db.insert_image(&image_uuid, &page, &image)).await?;
+
+            info!(image = %url, uuid = %uuid, "Face found, image saved");
+            let saved = CrawlEvent::ImageSaved {
+                uuid,
+                url: url.to_string(),
+                page_url: page_url.to_string(),
+                path: final_path.display().to_string(),
+            };
+            events.emit(saved).await;
             Ok(true)
         } else {
             // Delete image without face
             let _ = fs::remove_file(&temp_path).await;
-            if verbose {
-                println!("{}", format!("[Image] No face detected: {}", url).white());
-            }
+            debug!(image = %url, "No face detected");
             Ok(false)
         }
     }
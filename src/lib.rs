@@ -0,0 +1,56 @@
+//! Couscous Crawler - crawl websites and extract emails, phones and faces
+//!
+//! [`CrawlerBuilder`] configures a crawl and [`Crawler`] runs it, keeping the
+//! queue and findings in a [`Storage`] backend:
+//!
+//! ```no_run
+//! use couscous_crawler::{CrawlerBuilder, Database};
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let crawler = CrawlerBuilder::new("https://example.com/")
+//!     .storage(Arc::new(Database::new("emails.db")?))
+//!     .workers(4)
+//!     .max_pages(100)
+//!     .build()?;
+//! crawler.run().await?;
+//! println!("{} pages, stopped: {}", crawler.pages_crawled(), crawler.stop_reason());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The extractors in [`extractor`] also work on their own, on any HTML.
+
+pub mod budget;
+pub mod crawler;
+pub mod database;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod extractor;
+mod fingerprint;
+mod image_processor;
+pub mod maintenance;
+pub mod metrics;
+pub mod migrations;
+pub mod options;
+pub mod progress;
+pub mod reextract;
+pub mod report;
+mod robots;
+pub mod scope;
+mod seen;
+pub mod sink;
+pub mod stats;
+pub mod storage;
+pub mod strategy;
+pub mod summary;
+mod trap;
+mod warc;
+
+pub use crawler::{Crawler, CrawlerBuilder};
+pub use database::Database;
+pub use error::CrawlError;
+pub use events::{CrawlEvent, EventStream};
+pub use options::CrawlOptions;
+pub use storage::{JsonlStorage, MemoryStorage, Storage, StorageError};
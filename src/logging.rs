@@ -0,0 +1,60 @@
+use clap::ValueEnum;
+use couscous_crawler::progress::LogWriter;
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// Format of the diagnostics written to stderr
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the span fields
+    Json,
+}
+
+/// Filter used without `--log-filter` or `RUST_LOG`
+fn default_filter(verbose: bool) -> &'static str {
+    if verbose {
+        "warn,couscous_crawler=debug"
+    } else {
+        "warn,couscous_crawler=info"
+    }
+}
+
+/// Build the filter from `--log-filter`, then `RUST_LOG`, then the default level
+pub fn build_filter(directives: Option<&str>, verbose: bool) -> Result<EnvFilter, Box<dyn std::error::Error>> {
+    let directives = match directives {
+        Some(directives) => directives.to_string(),
+        None => std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_filter(verbose).to_string()),
+    };
+    EnvFilter::try_new(&directives).map_err(|e| format!("invalid log filter {:?}: {}", directives, e).into())
+}
+
+/// Install the global subscriber writing to stderr, stdout is kept for command output
+pub fn init(format: LogFormat, directives: Option<&str>, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = build_filter(directives, verbose)?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| LogWriter)
+        .with_ansi(std::io::stderr().is_terminal());
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_filter() {
+        let filter = build_filter(Some("couscous_crawler::crawler=trace,info"), false).unwrap();
+        assert!(filter.to_string().contains("couscous_crawler::crawler=trace"));
+        assert!(build_filter(Some("couscous_crawler=loud"), false).is_err());
+        assert!(build_filter(None, true).is_ok());
+    }
+}
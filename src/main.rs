@@ -1,59 +1,443 @@
 mod cli;
-mod crawler;
-mod database;
-mod extractor;
-mod image_processor;
-
-use cli::parse_args;
-use crawler::Crawler;
-use database::Database;
+mod config;
+mod logging;
+
+use clap::ArgMatches;
+use cli::{parse_args, Args, Cli, Command, ConfigAction};
+use colored::*;
+use config::Config;
+use couscous_crawler::export::{ExportFilter, ExportFormat, ExportKind};
+use couscous_crawler::maintenance::QueueStatus;
+use couscous_crawler::reextract::PageSource;
+use couscous_crawler::report::ReportFormat;
+use couscous_crawler::sink::{self, WebhookSink};
+use couscous_crawler::storage::StorageKind;
+use couscous_crawler::summary::{CrawlSummary, Counters, Outcome};
+use couscous_crawler::{crawler, export, maintenance, metrics, migrations, reextract, report, summary};
+use couscous_crawler::{CrawlOptions, CrawlerBuilder, Database, JsonlStorage, MemoryStorage, Storage};
+use rusqlite::{Connection, OpenFlags};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
 
+/// Events buffered for each output sink before the crawl waits on it
+const SINK_BUFFER: usize = 1024;
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = parse_args();
-    
-    println!("Couscous Crawler v0.1.0");
-    println!("==========================");
-    println!("Starting URL: {}", args.url);
-    println!("Depth limit: {}", if args.depth == 0 { "unlimited".to_string() } else { args.depth.to_string() });
-    println!("Stay on domain: {}", args.stay_on_domain);
-    println!("Workers: {}", args.workers);
-    println!("Database: {}", args.db);
-    println!("Resume: {}", args.resume);
-    println!();
+async fn main() {
+    let (cli, matches) = parse_args();
+    match run(cli, &matches).await {
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+        Err(e) => {
+            eprintln!("{}", format!("Error: {}", e).red());
+            std::process::exit(summary::EXIT_FATAL);
+        }
+    }
+}
 
-    // Initialize database
-    let db = Arc::new(Database::new(&args.db)?);
-    
-    // Create crawler
-    let crawler = Crawler::new(args.clone(), db.clone())?;
-    
-    // Initialize (queue start URL or resume)
-    crawler.init()?;
+/// Apply the configuration file and run the subcommand
+async fn run(mut cli: Cli, matches: &ArgMatches) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let config = Config::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    config.apply_globals(&mut cli, matches)?;
+    logging::init(cli.log_format, cli.log_filter.as_deref(), cli.verbose)?;
+    let db = cli.db.as_str();
+
+    let result = match cli.command {
+        Command::Crawl { url, mut args } => {
+            args.url = Some(url);
+            return crawl(crawl_settings(args, &cli.db, cli.verbose, &config, matches)?, &config, matches).await;
+        }
+        Command::Resume { run, url, retry_errors, mut args } => {
+            args.url = url;
+            args.resume = true;
+            args.resume_run = run;
+            args.retry_errors = retry_errors;
+            return crawl(crawl_settings(args, &cli.db, cli.verbose, &config, matches)?, &config, matches).await;
+        }
+        Command::Export {
+            kind,
+            format,
+            output,
+            columns,
+            run,
+            seed,
+            host,
+            email_domain,
+            since,
+            until,
+            min_confidence,
+        } => {
+            let filter = ExportFilter { run, seed, host, email_domain, since, until, min_confidence };
+            export(db, kind, format, output.as_deref(), &columns, &filter)
+        }
+        Command::Report { run, format, limit } => report(db, run, format, limit),
+        Command::Queue { run, status, limit } => queue(db, run, status, limit),
+        Command::Purge { run, keep_last, vacuum, dry_run } => purge(db, &run, keep_last, vacuum, dry_run),
+        Command::Config { action: ConfigAction::Show { args } } => {
+            config_show(&config, &crawl_settings(args, &cli.db, cli.verbose, &config, matches)?)
+        }
+        Command::Migrate { dry_run } => migrate(db, dry_run),
+        Command::Reextract { run, warc, dry_run } => reextract(db, run, &warc, dry_run),
+    };
+    result.map(|()| Outcome::Success)
+}
+
+/// Complete crawl settings with the global options and the configuration file
+fn crawl_settings(
+    mut args: Args,
+    db: &str,
+    verbose: bool,
+    config: &Config,
+    matches: &ArgMatches,
+) -> Result<Args, Box<dyn std::error::Error>> {
+    args.db = db.to_string();
+    args.verbose = verbose;
+    config.apply(&mut args, matches)?;
+    Ok(args)
+}
+
+/// Crawl from the starting URL and print statistics
+///
+/// A resumed run continues with the options it was started with, under the
+/// configuration file and the command line.
+async fn crawl(mut args: Args, config: &Config, matches: &ArgMatches) -> Result<Outcome, Box<dyn std::error::Error>> {
+    // Open the storage backend
+    if args.resume && args.storage != StorageKind::Sqlite {
+        return Err("resume needs --storage sqlite".into());
+    }
+    let db: Arc<dyn Storage> = match args.storage {
+        StorageKind::Sqlite => Arc::new(Database::new(&args.db)?),
+        StorageKind::Memory => Arc::new(MemoryStorage::new()),
+        StorageKind::Jsonl => Arc::new(JsonlStorage::open(&args.jsonl)?),
+    };
+    if args.resume {
+        let run = crawler::run_to_resume(db.as_ref(), args.resume_run)?;
+        config.apply_recorded(&mut args, &run.args, matches)?;
+        args.resume_run = Some(run.id);
+    }
+
+    // With --ndjson stdout only carries findings, the report goes to stderr
+    let mut out: Box<dyn Write> = if args.ndjson { Box::new(io::stderr()) } else { Box::new(io::stdout()) };
+    writeln!(out, "Couscous Crawler v0.1.0")?;
+    writeln!(out, "==========================")?;
+    if !args.resume {
+        writeln!(out, "Starting URL: {}", args.start_url())?;
+    }
+    writeln!(out, "Depth limit: {}", if args.crawl.depth == 0 { "unlimited".to_string() } else { args.crawl.depth.to_string() })?;
+    writeln!(out, "Stay on domain: {}", args.crawl.stay_on_domain)?;
+    writeln!(out, "Workers: {}", args.crawl.workers)?;
+    writeln!(out, "Strategy: {:?}", args.crawl.strategy)?;
+    writeln!(out, "Storage: {}", storage_target(&args))?;
+    writeln!(out, "Resume: {}", args.resume)?;
+    writeln!(out)?;
+
+    // Start or resume the run
+    let builder = if args.resume {
+        let builder = CrawlerBuilder::resume(args.resume_run).retry_errors(args.retry_errors);
+        match &args.url {
+            Some(url) => builder.seed(url),
+            None => builder,
+        }
+    } else {
+        CrawlerBuilder::new(args.start_url())
+    };
+    // The live display draws on stdout, which --ndjson keeps for findings
+    let progress = !(args.ndjson && io::stdout().is_terminal());
+    let crawler = builder.options(args.crawl.clone()).storage(db).progress(progress).build()?;
+    let db = crawler.storage().clone();
+
+    if let Some(addr) = &args.metrics_addr {
+        let addr = metrics::serve(addr, db.clone()).await?;
+        writeln!(out, "Metrics: http://{}/metrics", addr)?;
+    }
+
+    // Output sinks, in addition to the storage
+    let ndjson = args.ndjson.then(|| tokio::spawn(sink::write_ndjson(crawler.subscribe(SINK_BUFFER), tokio::io::stdout())));
+    let webhook = match &args.webhook {
+        Some(url) => {
+            let webhook = WebhookSink::new(url)?
+                .batch_size(args.webhook_batch_size)
+                .max_retries(args.webhook_retries);
+            Some(tokio::spawn(webhook.run(crawler.subscribe(SINK_BUFFER))))
+        }
+        None => None,
+    };
     
+    let started_at = summary::timestamp();
     let start_time = Instant::now();
     
-    println!("Starting crawl...\n");
+    writeln!(out, "Starting crawl...\n")?;
     crawler.run().await?;
     
     let elapsed = start_time.elapsed();
+    if let Some(ndjson) = ndjson {
+        ndjson.await??;
+    }
+    let webhook = match webhook {
+        Some(webhook) => Some(webhook.await?),
+        None => None,
+    };
+
+    let session = crawler.stats().snapshot();
+    let mut errors = crawler.stats().errors_by_kind();
+    // Findings the webhook could not deliver count as errors
+    let dropped = webhook.map_or(0, |webhook| webhook.dropped);
+    if dropped > 0 {
+        errors.insert("webhook".to_string(), dropped);
+    }
+    let error_count = session.errors + dropped;
+    let outcome = Outcome::from_errors(error_count);
     
     // Print statistics
     let (unique_emails, total_entries) = db.get_stats()?;
     let unique_phones = db.get_phone_count()?;
     let images_saved = db.get_image_count()?;
+    let trap_patterns = db.get_trap_count()?;
+    let duplicate_pages = db.get_duplicate_count()?;
+    let run_errors = db.get_error_count()?;
     
+    writeln!(out)?;
+    writeln!(out, "==========================")?;
+    writeln!(out, "Crawl complete!")?;
+    writeln!(out, "Run: #{}", crawler.run_id())?;
+    writeln!(out, "Stopped: {}", crawler.stop_reason())?;
+    writeln!(out, "Time elapsed: {:.2}s", elapsed.as_secs_f64())?;
+    writeln!(out, "Pages crawled: {}", crawler.pages_crawled())?;
+    writeln!(out, "Unique emails found: {}", unique_emails)?;
+    writeln!(out, "Unique phones found: {}", unique_phones)?;
+    writeln!(out, "Images with faces: {}", images_saved)?;
+    writeln!(out, "Total email entries: {}", total_entries)?;
+    writeln!(out, "Trapped URL patterns: {}", trap_patterns)?;
+    writeln!(out, "Duplicate pages skipped: {}", duplicate_pages)?;
+    if errors.is_empty() {
+        writeln!(out, "Errors: 0")?;
+    } else {
+        let kinds: Vec<String> = errors.iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+        writeln!(out, "{}", format!("Errors: {} ({})", error_count, kinds.join(", ")).red())?;
+        if args.storage == StorageKind::Sqlite && session.errors > 0 {
+            writeln!(out, "Retry failed URLs with: resume --run {} --retry-errors", crawler.run_id())?;
+        }
+    }
+    if let Some(webhook) = webhook {
+        writeln!(
+            out,
+            "Webhook: {} findings sent in {} batches, {} dropped",
+            webhook.sent, webhook.batches, webhook.dropped
+        )?;
+    }
+    if args.storage != StorageKind::Memory {
+        writeln!(out, "Results saved to: {}", storage_target(&args))?;
+    }
+
+    if let Some(path) = &args.summary_json {
+        let summary = CrawlSummary {
+            run: crawler.run_id(),
+            start_url: crawler.start_url().to_string(),
+            outcome,
+            exit_code: outcome.exit_code(),
+            stop_reason: crawler.stop_reason().to_string(),
+            started_at,
+            finished_at: summary::timestamp(),
+            session_secs: elapsed.as_secs_f64(),
+            crawl_secs: crawler.crawl_time().as_secs_f64(),
+            counters: Counters {
+                pages_crawled: crawler.pages_crawled(),
+                session_pages: session.pages,
+                bytes_downloaded: crawler.bytes_downloaded(),
+                new_emails: session.emails,
+                new_phones: session.phones,
+                new_images: session.images,
+                unique_emails,
+                unique_phones,
+                total_email_entries: total_entries,
+                images_with_faces: images_saved,
+                trap_patterns,
+                duplicate_pages,
+                errors: error_count,
+                run_errors,
+            },
+            status_codes: crawler.stats().status_counts(),
+            errors,
+            top_hosts: summary::top_hosts(db.get_host_usage()?),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&summary)? + "\n")?;
+        writeln!(out, "Summary written to: {}", path)?;
+    }
+
+    Ok(outcome)
+}
+
+/// Where the selected storage backend keeps its data
+fn storage_target(args: &Args) -> String {
+    match args.storage {
+        StorageKind::Sqlite => args.db.clone(),
+        StorageKind::Memory => "memory".to_string(),
+        StorageKind::Jsonl => args.jsonl.clone(),
+    }
+}
+
+/// Print the effective crawl settings as a configuration file
+fn config_show(config: &Config, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    match (&config.path, &config.profile) {
+        (Some(path), Some(profile)) => println!("# Configuration: {} (profile: {})", path, profile),
+        (Some(path), None) => println!("# Configuration: {}", path),
+        _ => println!("# No configuration file, defaults and command line only"),
+    }
+    print!("{}", config::to_toml(args)?);
+    Ok(())
+}
+
+/// Upgrade the database schema, or list pending migrations with `dry_run`
+fn migrate(path: &str, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // Never create the database, a mistyped path would get an empty one;
+    // a dry run leaves it untouched
+    let flags = if dry_run { OpenFlags::SQLITE_OPEN_READ_ONLY } else { OpenFlags::SQLITE_OPEN_READ_WRITE };
+    let mut conn = match Connection::open_with_flags(path, flags) {
+        Err(_) if !Path::new(path).exists() => return Err(format!("No database at {}", path).into()),
+        result => result?,
+    };
+    let version = migrations::check_version(&conn)?;
+    let pending = migrations::pending(&conn)?;
+
+    println!("Database: {}", path);
+    println!("Schema version: {} (latest: {})", version, migrations::latest_version());
+
+    if pending.is_empty() {
+        println!("Schema is up to date");
+        return Ok(());
+    }
+
+    for migration in &pending {
+        println!("Pending: v{} {}", migration.version, migration.description);
+    }
+
+    if !dry_run {
+        let applied = migrations::migrate(&mut conn)?;
+        println!("Applied {} migration(s)", applied.len());
+    }
+
+    Ok(())
+}
+
+/// Re-extract findings of a run from its stored pages or WARC files
+fn reextract(path: &str, run: Option<i64>, warc: &[String], dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::new(path)?;
+    let run = db.find_run(run)?.ok_or("No run to re-extract")?;
+    let db = db.for_run(run.id);
+    let options = CrawlOptions::from_recorded(&run.args);
+    let source = if warc.is_empty() { PageSource::Database } else { PageSource::Warc(warc) };
+
+    println!("Re-extracting run #{} from {}", run.id, if warc.is_empty() { "the page store" } else { "WARC files" });
+    let report = reextract::reextract(&db, &options, source, dry_run)?;
+
+    for change in &report.changes {
+        let line = format!("{} {} on {}", change.kind, change.value, change.url);
+        if change.added {
+            println!("{}", format!("+ {}", line).green());
+        } else {
+            println!("{}", format!("- {}", line).red());
+        }
+    }
     println!();
-    println!("==========================");
-    println!("Crawl complete!");
-    println!("Time elapsed: {:.2}s", elapsed.as_secs_f64());
-    println!("Unique emails found: {}", unique_emails);
-    println!("Unique phones found: {}", unique_phones);
-    println!("Images with faces: {}", images_saved);
-    println!("Total email entries: {}", total_entries);
-    println!("Results saved to: {}", args.db);
-    
+    println!("Pages processed: {}", report.pages);
+    println!("Pages skipped: {}", report.skipped);
+    println!("Emails added: {}, removed: {}", report.emails_added, report.emails_removed);
+    println!("Phones added: {}, removed: {}", report.phones_added, report.phones_removed);
+    if dry_run {
+        println!("Dry run, nothing was changed");
+    }
+
+    Ok(())
+}
+
+/// Export a table of the database to a file or stdout
+fn export(
+    path: &str,
+    kind: ExportKind,
+    format: ExportFormat,
+    output: Option<&str>,
+    columns: &[String],
+    filter: &ExportFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Database::open_read_only(path)?;
+    let mut out: Box<dyn std::io::Write> = match output {
+        Some(file) => Box::new(std::io::BufWriter::new(std::fs::File::create(file)?)),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
+    let count = export::export(&conn, kind, format, filter, columns, &mut out)?;
+
+    // Keep stdout clean for the exported data
+    eprintln!("Exported {} rows", count);
+    Ok(())
+}
+
+/// Print the crawl report of a run, or of all runs
+fn report(path: &str, run: Option<i64>, format: ReportFormat, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Database::open_read_only(path)?;
+    let report = report::build_report(&conn, run, limit)?;
+    match format {
+        ReportFormat::Table => report::print_report(&report),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+/// Print a run's queue counts and the first URLs with a status
+fn queue(path: &str, run: Option<i64>, status: QueueStatus, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Database::open_read_only(path)?;
+    let summary = maintenance::queue_summary(&conn, run, status, limit)?.ok_or("No such run")?;
+
+    println!("Queue of run #{}", summary.run);
+    for (status, count) in &summary.counts {
+        println!("  {:<12}{:>8}", status, count);
+    }
+    if !summary.urls.is_empty() {
+        println!();
+        println!("{:?} URLs (depth, priority):", status);
+        for (url, depth, priority) in &summary.urls {
+            println!("  {} ({}, {})", url, depth, priority);
+        }
+    }
+    Ok(())
+}
+
+/// Delete runs selected by id or by keeping only the latest ones
+fn purge(
+    path: &str,
+    runs: &[i64],
+    keep_last: Option<usize>,
+    vacuum: bool,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if runs.is_empty() && keep_last.is_none() {
+        return Err("Nothing to purge, pass --run or --keep-last".into());
+    }
+    // A dry run leaves the database untouched, schema included
+    let mut conn = if dry_run {
+        Database::open_read_only(path)?
+    } else {
+        let mut conn = Connection::open(path)?;
+        migrations::migrate(&mut conn)?;
+        conn
+    };
+
+    let selected = maintenance::runs_to_purge(&conn, runs, keep_last)?;
+    if selected.is_empty() {
+        println!("No runs to purge");
+        return Ok(());
+    }
+    let ids: Vec<String> = selected.iter().map(|id| format!("#{}", id)).collect();
+    if dry_run {
+        println!("Would delete runs {}", ids.join(", "));
+        return Ok(());
+    }
+
+    let pages = maintenance::purge_runs(&mut conn, &selected)?;
+    println!("Deleted runs {} ({} pages)", ids.join(", "), pages);
+    if vacuum {
+        conn.execute_batch("VACUUM")?;
+        println!("Database vacuumed");
+    }
     Ok(())
 }
@@ -0,0 +1,166 @@
+use clap::ValueEnum;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+
+/// Status of a queued URL
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueStatus {
+    Pending,
+    Processing,
+    /// Out of host budget, kept for a resume
+    Deferred,
+    Done,
+}
+
+impl QueueStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            QueueStatus::Pending => "pending",
+            QueueStatus::Processing => "processing",
+            QueueStatus::Deferred => "deferred",
+            QueueStatus::Done => "done",
+        }
+    }
+}
+
+/// Queue of a run, counted by status
+#[derive(Debug, Default)]
+pub struct QueueSummary {
+    pub run: i64,
+    pub counts: Vec<(String, u64)>,
+    /// First URLs with the requested status as (url, depth, priority)
+    pub urls: Vec<(String, u32, i64)>,
+}
+
+/// Count the queue of a run (the latest if unset) and list up to `limit` URLs with `status`
+pub fn queue_summary(
+    conn: &Connection,
+    run: Option<i64>,
+    status: QueueStatus,
+    limit: usize,
+) -> Result<Option<QueueSummary>> {
+    let run: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM runs WHERE ?1 IS NULL OR id = ?1 ORDER BY id DESC LIMIT 1",
+            params![run],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(run) = run else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT status, COUNT(*) FROM url_queue WHERE run_id = ?1 GROUP BY status ORDER BY status",
+    )?;
+    let counts = stmt
+        .query_map(params![run], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT url, depth, priority FROM url_queue
+         WHERE run_id = ?1 AND status = ?2
+         ORDER BY id LIMIT ?3",
+    )?;
+    let urls = stmt
+        .query_map(params![run, status.as_str(), limit as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<Result<_>>()?;
+
+    Ok(Some(QueueSummary { run, counts, urls }))
+}
+
+/// Runs to purge: the given ones, plus all but the latest `keep_last`
+pub fn runs_to_purge(conn: &Connection, runs: &[i64], keep_last: Option<usize>) -> Result<Vec<i64>> {
+    let mut selected = Vec::new();
+    let mut stmt = conn.prepare("SELECT id FROM runs ORDER BY id DESC")?;
+    let all: Vec<i64> = stmt.query_map([], |row| row.get(0))?.collect::<Result<_>>()?;
+    for (i, id) in all.into_iter().enumerate() {
+        if runs.contains(&id) || keep_last.is_some_and(|keep| i >= keep) {
+            selected.push(id);
+        }
+    }
+    selected.sort();
+    Ok(selected)
+}
+
+/// Delete runs with their queue, pages, sightings and images, in one transaction
+///
+/// Emails and phones only seen in the deleted runs are removed, the others get
+/// their counts recomputed. Returns how many pages were deleted.
+pub fn purge_runs(conn: &mut Connection, runs: &[i64]) -> Result<u64> {
+    let tx = conn.transaction()?;
+    let mut pages = 0;
+    for run in runs {
+        tx.execute_batch(&format!(
+            "DELETE FROM email_sightings WHERE page_id IN (SELECT id FROM pages WHERE run_id = {run});
+             DELETE FROM phone_sightings WHERE page_id IN (SELECT id FROM pages WHERE run_id = {run});
+             DELETE FROM images WHERE page_id IN (SELECT id FROM pages WHERE run_id = {run});
+             DELETE FROM page_contents WHERE page_id IN (SELECT id FROM pages WHERE run_id = {run});
+             DELETE FROM url_queue WHERE run_id = {run};
+             DELETE FROM visited WHERE run_id = {run};
+             DELETE FROM host_budget WHERE run_id = {run};
+             DELETE FROM errors WHERE run_id = {run};"
+        ))?;
+        pages += tx.execute("DELETE FROM pages WHERE run_id = ?1", params![run])? as u64;
+        tx.execute("DELETE FROM runs WHERE id = ?1", params![run])?;
+    }
+
+    for (table, column) in [("emails", "email"), ("phones", "phone")] {
+        tx.execute_batch(&format!(
+            "UPDATE {table} SET
+                occurrences = (SELECT COUNT(*) FROM {column}_sightings s WHERE s.{column}_id = {table}.id),
+                url_count = (
+                    SELECT COUNT(DISTINCT p.url) FROM {column}_sightings s
+                    JOIN pages p ON p.id = s.page_id WHERE s.{column}_id = {table}.id
+                );
+             DELETE FROM {table} WHERE occurrences = 0;"
+        ))?;
+    }
+    tx.commit()?;
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDb;
+
+    #[test]
+    fn test_queue_summary_and_purge() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        for seed in ["https://a.fr/", "https://b.fr/", "https://c.fr/"] {
+            let run = db.for_run(db.begin_run(&format!("[\"{}\"]", seed), "{}").unwrap());
+            run.queue_urls(vec![(seed.to_string(), 1, 0), (format!("{}contact", seed), 2, 90)]).unwrap();
+            run.insert_page(seed, Some(200), None, None).unwrap();
+            run.insert_emails(vec!["shared@x.fr".to_string(), format!("only@{}", &seed[8..12])], seed).unwrap();
+        }
+        db.for_run(3).complete_url("https://c.fr/").unwrap();
+        drop(db);
+
+        let mut conn = Connection::open(temp.path()).unwrap();
+        let summary = queue_summary(&conn, None, QueueStatus::Pending, 10).unwrap().unwrap();
+        assert_eq!(summary.run, 3);
+        assert_eq!(summary.counts, vec![("done".to_string(), 1), ("pending".to_string(), 1)]);
+        assert_eq!(summary.urls, vec![("https://c.fr/contact".to_string(), 2, 90)]);
+        assert!(queue_summary(&conn, Some(9), QueueStatus::Pending, 10).unwrap().is_none());
+
+        let runs = runs_to_purge(&conn, &[2], Some(2)).unwrap();
+        assert_eq!(runs, vec![1, 2]);
+        assert_eq!(purge_runs(&mut conn, &runs).unwrap(), 2);
+
+        let remaining: Vec<(String, u64)> = conn
+            .prepare("SELECT email, occurrences FROM emails ORDER BY email")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(remaining, vec![("only@c.fr".to_string(), 1), ("shared@x.fr".to_string(), 1)]);
+        let queued: u64 = conn.query_row("SELECT COUNT(*) FROM url_queue", [], |row| row.get(0)).unwrap();
+        assert_eq!(queued, 2);
+    }
+}
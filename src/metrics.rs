@@ -0,0 +1,161 @@
+use crate::storage::{self, Storage};
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Registry = Registry::new_custom(Some("couscous".to_string()), None).unwrap();
+
+    /// Fetched pages by status class (`2xx` to `5xx`, `error` when no response)
+    pub static ref PAGES_FETCHED: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("pages_fetched_total", "Pages fetched, by HTTP status class"),
+        &["status"],
+    ).unwrap());
+
+    pub static ref FETCH_DURATION: Histogram = register(Histogram::with_opts(
+        HistogramOpts::new("fetch_duration_seconds", "Time to fetch a page, body included")
+            .buckets(exponential_buckets(0.025, 2.0, 10).unwrap()),
+    ).unwrap());
+
+    pub static ref BYTES_DOWNLOADED: IntCounter = register(IntCounter::new(
+        "downloaded_bytes_total", "Bytes of page bodies downloaded",
+    ).unwrap());
+
+    /// Pending URLs of the run, refreshed on each scrape
+    pub static ref QUEUE_DEPTH: IntGauge = register(IntGauge::new(
+        "queue_depth", "URLs waiting in the queue",
+    ).unwrap());
+
+    /// New findings stored, by kind (`email`, `phone`, `image`)
+    pub static ref FINDINGS_INSERTED: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("findings_inserted_total", "New findings stored, by kind"),
+        &["kind"],
+    ).unwrap());
+
+    /// Processed images by outcome (`face`, `no_face`, `too_small`, `not_image`, `download_error`)
+    pub static ref IMAGE_DETECTIONS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("image_detections_total", "Images processed, by face detection outcome"),
+        &["outcome"],
+    ).unwrap());
+
+    pub static ref DB_WRITE_DURATION: Histogram = register(Histogram::with_opts(
+        HistogramOpts::new("db_write_duration_seconds", "Time for a database write to be committed")
+            .buckets(exponential_buckets(0.0005, 2.0, 12).unwrap()),
+    ).unwrap());
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: T) -> T {
+    REGISTRY.register(Box::new(metric.clone())).unwrap();
+    metric
+}
+
+/// Label of an HTTP status for `PAGES_FETCHED`
+pub fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// All metrics in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    encoder.encode(&REGISTRY.gather(), &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Serve the metrics over HTTP on `addr` until the process exits, returns the bound address
+pub async fn serve(addr: &str, db: Arc<dyn Storage>) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(respond(stream, db.clone()));
+                }
+                Err(e) => warn!(error = %e, "Metrics connection failed"),
+            }
+        }
+    });
+    Ok(local_addr)
+}
+
+/// Answer one request, `GET /metrics` gets the metrics and anything else a 404
+async fn respond(mut stream: TcpStream, db: Arc<dyn Storage>) {
+    // Only the request line matters, read until the end of the headers
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next(), request_line.next());
+
+    let (status, content_type, body) = if method == Some("GET") && path == Some("/metrics") {
+        if let Ok(pending) = storage::call(&db, |db| db.pending_count()).await {
+            QUEUE_DEPTH.set(pending as i64);
+        }
+        ("200 OK", TextEncoder::new().format_type().to_string(), render())
+    } else {
+        ("404 Not Found", "text/plain; charset=utf-8".to_string(), "Not found, try /metrics\n".to_string())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!(error = %e, "Can't send metrics");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics() {
+        let db: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let db = db.for_run(db.begin_run("[]", "{}").unwrap());
+        db.queue_urls(vec![("https://a.fr/".to_string(), 1, 0), ("https://a.fr/b".to_string(), 2, 0)]).unwrap();
+        PAGES_FETCHED.with_label_values(&[status_class(404)]).inc();
+        FETCH_DURATION.observe(0.3);
+
+        let addr = serve("127.0.0.1:0", db).await.unwrap();
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("couscous_pages_fetched_total{status=\"4xx\"}"));
+        assert!(response.contains("couscous_fetch_duration_seconds_bucket"));
+        assert!(response.contains("couscous_queue_depth 2"));
+
+        assert!(get(addr, "/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,676 @@
+use rusqlite::{Connection, Transaction};
+use std::fmt;
+
+/// A schema change applied in order, tracked with `PRAGMA user_version`
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// All migrations, in order. Never edit a released migration, add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        apply: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "crawl runs",
+        apply: crawl_runs,
+    },
+    Migration {
+        version: 3,
+        description: "normalized findings",
+        apply: normalized_findings,
+    },
+    Migration {
+        version: 4,
+        description: "page content store",
+        apply: page_contents,
+    },
+    Migration {
+        version: 5,
+        description: "page status codes",
+        apply: page_status,
+    },
+    Migration {
+        version: 6,
+        description: "crawl errors",
+        apply: crawl_errors,
+    },
+    Migration {
+        version: 7,
+        description: "image URLs",
+        apply: image_urls,
+    },
+];
+
+/// Schema version this build writes
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Error opening or migrating the database schema
+#[derive(Debug)]
+pub enum SchemaError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer version of the crawler
+    TooNew { found: u32, supported: u32 },
+    /// The database needs migrating and was opened read-only
+    Outdated { found: u32, latest: u32 },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Sqlite(e) => write!(f, "{}", e),
+            SchemaError::TooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the supported version {}, upgrade couscous-crawler",
+                found, supported
+            ),
+            SchemaError::Outdated { found, latest } => write!(
+                f,
+                "database schema version {} is older than the latest version {}, run `migrate` first",
+                found, latest
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<rusqlite::Error> for SchemaError {
+    fn from(e: rusqlite::Error) -> Self {
+        SchemaError::Sqlite(e)
+    }
+}
+
+/// Read the schema version of a database
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Refuse databases written by a newer version
+pub fn check_version(conn: &Connection) -> Result<u32, SchemaError> {
+    let found = schema_version(conn)?;
+    let supported = latest_version();
+    if found > supported {
+        return Err(SchemaError::TooNew { found, supported });
+    }
+    Ok(found)
+}
+
+/// Migrations not yet applied to a database
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, SchemaError> {
+    let current = check_version(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// Apply pending migrations, each in its own transaction, returns the applied ones
+pub fn migrate(conn: &mut Connection) -> Result<Vec<&'static Migration>, SchemaError> {
+    let pending = pending(conn)?;
+    for migration in &pending {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+    Ok(pending)
+}
+
+/// Add a column unless it already exists
+///
+/// Databases created before versioning may already have columns added later.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+/// Version 1: the schema as it was before versioning
+///
+/// Every statement is idempotent because unversioned databases may hold any
+/// subset of these tables.
+fn initial_schema(conn: &Transaction) -> rusqlite::Result<()> {
+    
+    // Emails table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS emails (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email TEXT NOT NULL,
+            source_url TEXT NOT NULL,
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(email, source_url)
+        )",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_email ON emails(email)",
+        [],
+    )?;
+
+    // URL queue table for persistence
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS url_queue (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT NOT NULL UNIQUE,
+            depth INTEGER NOT NULL,
+            status TEXT DEFAULT 'pending',
+            priority INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Databases created before crawl strategies lack the priority column
+    add_column_if_missing(conn, "url_queue", "priority", "INTEGER NOT NULL DEFAULT 0")?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_queue_status ON url_queue(status)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_queue_priority ON url_queue(status, priority)",
+        [],
+    )?;
+
+    // Visited URLs table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS visited (
+            url TEXT PRIMARY KEY
+        )",
+        [],
+    )?;
+
+    // Phones table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS phones (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            phone TEXT NOT NULL,
+            source_url TEXT NOT NULL,
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(phone, source_url)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_phone ON phones(phone)",
+        [],
+    )?;
+
+    // Images table (for face detection)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS images (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uuid TEXT NOT NULL UNIQUE,
+            source_url TEXT NOT NULL,
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // Crawl trap patterns (for review)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS traps (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            pattern TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            example_url TEXT NOT NULL,
+            hits INTEGER NOT NULL DEFAULT 1,
+            first_seen TEXT DEFAULT CURRENT_TIMESTAMP,
+            last_seen TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(pattern, reason)
+        )",
+        [],
+    )?;
+
+    // Fetched pages with content fingerprints
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT NOT NULL UNIQUE,
+            content_hash INTEGER,
+            simhash INTEGER,
+            duplicate_of TEXT,
+            fetched_at TEXT DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_pages_content_hash ON pages(content_hash)",
+        [],
+    )?;
+
+    // Budget usage per host, kept so --resume can continue a stopped crawl
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS host_budget (
+            host TEXT PRIMARY KEY,
+            pages INTEGER NOT NULL DEFAULT 0,
+            bytes INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Crawl-wide state (elapsed time, stop reason)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS crawl_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 2: a `runs` table, with findings, pages, queue and budgets tagged by run
+///
+/// Findings from before this version keep a NULL run id. An unfinished queue is
+/// adopted by a legacy run so it can still be resumed.
+fn crawl_runs(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            started_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            finished_at TEXT,
+            seeds TEXT NOT NULL,
+            args TEXT NOT NULL,
+            stop_reason TEXT,
+            elapsed_ms INTEGER NOT NULL DEFAULT 0,
+            pages INTEGER NOT NULL DEFAULT 0,
+            emails INTEGER NOT NULL DEFAULT 0,
+            phones INTEGER NOT NULL DEFAULT 0,
+            images INTEGER NOT NULL DEFAULT 0
+        );
+
+        INSERT INTO runs (seeds, args, stop_reason, elapsed_ms)
+        SELECT
+            '[]',
+            '{}',
+            (SELECT value FROM crawl_state WHERE key = 'stop_reason'),
+            COALESCE((SELECT CAST(value AS INTEGER) FROM crawl_state WHERE key = 'elapsed_ms'), 0)
+        WHERE EXISTS (SELECT 1 FROM url_queue);
+
+        CREATE TABLE emails_v2 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email TEXT NOT NULL,
+            source_url TEXT NOT NULL,
+            run_id INTEGER REFERENCES runs(id),
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(email, source_url, run_id)
+        );
+        INSERT INTO emails_v2 (id, email, source_url, found_at)
+            SELECT id, email, source_url, found_at FROM emails;
+        DROP TABLE emails;
+        ALTER TABLE emails_v2 RENAME TO emails;
+        CREATE INDEX idx_email ON emails(email);
+        CREATE INDEX idx_emails_run ON emails(run_id);
+
+        CREATE TABLE phones_v2 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            phone TEXT NOT NULL,
+            source_url TEXT NOT NULL,
+            run_id INTEGER REFERENCES runs(id),
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(phone, source_url, run_id)
+        );
+        INSERT INTO phones_v2 (id, phone, source_url, found_at)
+            SELECT id, phone, source_url, found_at FROM phones;
+        DROP TABLE phones;
+        ALTER TABLE phones_v2 RENAME TO phones;
+        CREATE INDEX idx_phone ON phones(phone);
+        CREATE INDEX idx_phones_run ON phones(run_id);
+
+        ALTER TABLE images ADD COLUMN run_id INTEGER REFERENCES runs(id);
+        CREATE INDEX idx_images_run ON images(run_id);
+
+        CREATE TABLE pages_v2 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT NOT NULL,
+            run_id INTEGER REFERENCES runs(id),
+            content_hash INTEGER,
+            simhash INTEGER,
+            duplicate_of TEXT,
+            fetched_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(run_id, url)
+        );
+        INSERT INTO pages_v2 (id, url, content_hash, simhash, duplicate_of, fetched_at)
+            SELECT id, url, content_hash, simhash, duplicate_of, fetched_at FROM pages;
+        DROP TABLE pages;
+        ALTER TABLE pages_v2 RENAME TO pages;
+        CREATE INDEX idx_pages_content_hash ON pages(content_hash);
+
+        CREATE TABLE url_queue_v2 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL REFERENCES runs(id),
+            url TEXT NOT NULL,
+            depth INTEGER NOT NULL,
+            status TEXT DEFAULT 'pending',
+            priority INTEGER NOT NULL DEFAULT 0,
+            UNIQUE(run_id, url)
+        );
+        INSERT INTO url_queue_v2 (id, run_id, url, depth, status, priority)
+            SELECT id, (SELECT MAX(id) FROM runs), url, depth, status, priority FROM url_queue;
+        DROP TABLE url_queue;
+        ALTER TABLE url_queue_v2 RENAME TO url_queue;
+        CREATE INDEX idx_queue_status ON url_queue(run_id, status);
+        CREATE INDEX idx_queue_priority ON url_queue(run_id, status, priority);
+
+        CREATE TABLE visited_v2 (
+            run_id INTEGER NOT NULL REFERENCES runs(id),
+            url TEXT NOT NULL,
+            PRIMARY KEY (run_id, url)
+        );
+        INSERT INTO visited_v2 (run_id, url)
+            SELECT (SELECT MAX(id) FROM runs), url FROM visited WHERE EXISTS (SELECT 1 FROM runs);
+        DROP TABLE visited;
+        ALTER TABLE visited_v2 RENAME TO visited;
+
+        CREATE TABLE host_budget_v2 (
+            run_id INTEGER NOT NULL REFERENCES runs(id),
+            host TEXT NOT NULL,
+            pages INTEGER NOT NULL DEFAULT 0,
+            bytes INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (run_id, host)
+        );
+        INSERT INTO host_budget_v2 (run_id, host, pages, bytes)
+            SELECT (SELECT MAX(id) FROM runs), host, pages, bytes FROM host_budget
+            WHERE EXISTS (SELECT 1 FROM runs);
+        DROP TABLE host_budget;
+        ALTER TABLE host_budget_v2 RENAME TO host_budget;
+
+        DROP TABLE crawl_state;",
+    )
+}
+
+/// Version 3: findings reference pages by id, with sighting timestamps and counts
+///
+/// `emails` and `phones` hold one row per value with `first_seen`, `last_seen`,
+/// `occurrences` (sightings across all runs) and `url_count` (distinct source
+/// URLs). Each sighting on a page is a row of `email_sightings`/`phone_sightings`.
+/// Source URLs of older findings that were never fetched as pages get a page row.
+fn normalized_findings(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE pages_v3 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER REFERENCES runs(id),
+            url TEXT NOT NULL,
+            content_hash INTEGER,
+            simhash INTEGER,
+            duplicate_of INTEGER REFERENCES pages_v3(id),
+            fetched_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(run_id, url)
+        );
+        INSERT INTO pages_v3 (id, run_id, url, content_hash, simhash, duplicate_of, fetched_at)
+            SELECT p.id, p.run_id, p.url, p.content_hash, p.simhash,
+                (SELECT o.id FROM pages o WHERE o.url = p.duplicate_of AND o.run_id IS p.run_id),
+                p.fetched_at
+            FROM pages p;
+        INSERT INTO pages_v3 (run_id, url, content_hash, fetched_at)
+            SELECT f.run_id, f.source_url, NULL, MIN(f.found_at)
+            FROM (
+                SELECT run_id, source_url, found_at FROM emails
+                UNION ALL SELECT run_id, source_url, found_at FROM phones
+                UNION ALL SELECT run_id, source_url, found_at FROM images
+            ) f
+            WHERE NOT EXISTS (SELECT 1 FROM pages p WHERE p.url = f.source_url AND p.run_id IS f.run_id)
+            GROUP BY f.run_id, f.source_url;
+        DROP TABLE pages;
+        ALTER TABLE pages_v3 RENAME TO pages;
+        CREATE INDEX idx_pages_content_hash ON pages(content_hash);
+        CREATE INDEX idx_pages_url ON pages(url);
+
+        CREATE TABLE images_v3 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uuid TEXT NOT NULL UNIQUE,
+            page_id INTEGER NOT NULL REFERENCES pages(id),
+            found_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        INSERT INTO images_v3 (id, uuid, page_id, found_at)
+            SELECT i.id, i.uuid, p.id, i.found_at
+            FROM images i JOIN pages p ON p.url = i.source_url AND p.run_id IS i.run_id;
+        DROP TABLE images;
+        ALTER TABLE images_v3 RENAME TO images;
+        CREATE INDEX idx_images_page ON images(page_id);",
+    )?;
+
+    normalize_findings(conn, "emails", "email")?;
+    normalize_findings(conn, "phones", "phone")
+}
+
+/// Version 4: gzipped page content, kept with `--store-pages` for re-extraction
+fn page_contents(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE page_contents (
+            page_id INTEGER PRIMARY KEY REFERENCES pages(id),
+            body BLOB NOT NULL
+        );",
+    )
+}
+
+/// Version 5: HTTP status code of each fetched page (NULL for older pages)
+fn page_status(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch("ALTER TABLE pages ADD COLUMN status INTEGER;")
+}
+
+/// Version 6: failed fetches and processing errors, `retried` once requeued by `resume --retry-errors`
+fn crawl_errors(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE errors (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL REFERENCES runs(id),
+            url TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL,
+            retryable INTEGER NOT NULL DEFAULT 0,
+            retried INTEGER NOT NULL DEFAULT 0,
+            occurred_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_errors_run ON errors(run_id, kind);",
+    )
+}
+
+/// Version 7: URL of each saved image, now attached to the page that referenced it
+///
+/// Older images keep a NULL `image_url`: their page row is the image URL itself.
+fn image_urls(conn: &Transaction) -> rusqlite::Result<()> {
+    conn.execute_batch("ALTER TABLE images ADD COLUMN image_url TEXT;")
+}
+
+/// Split a v2 findings table into a value table and a sightings table
+fn normalize_findings(conn: &Transaction, table: &str, column: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "ALTER TABLE {table} RENAME TO {table}_v2;
+        DROP INDEX idx_{column};
+        DROP INDEX idx_{table}_run;
+
+        CREATE TABLE {table} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            {column} TEXT NOT NULL UNIQUE,
+            first_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            last_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            occurrences INTEGER NOT NULL DEFAULT 0,
+            url_count INTEGER NOT NULL DEFAULT 0
+        );
+        INSERT INTO {table} ({column}, first_seen, last_seen)
+            SELECT {column}, MIN(COALESCE(found_at, CURRENT_TIMESTAMP)), MAX(COALESCE(found_at, CURRENT_TIMESTAMP))
+            FROM {table}_v2 GROUP BY {column};
+
+        CREATE TABLE {column}_sightings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            {column}_id INTEGER NOT NULL REFERENCES {table}(id),
+            page_id INTEGER NOT NULL REFERENCES pages(id),
+            first_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            last_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE({column}_id, page_id)
+        );
+        INSERT INTO {column}_sightings ({column}_id, page_id, first_seen, last_seen)
+            SELECT v.id, p.id, COALESCE(o.found_at, CURRENT_TIMESTAMP), COALESCE(o.found_at, CURRENT_TIMESTAMP)
+            FROM {table}_v2 o
+            JOIN {table} v ON v.{column} = o.{column}
+            JOIN pages p ON p.url = o.source_url AND p.run_id IS o.run_id;
+        CREATE INDEX idx_{column}_sightings_page ON {column}_sightings(page_id);
+
+        UPDATE {table} SET
+            occurrences = (SELECT COUNT(*) FROM {column}_sightings s WHERE s.{column}_id = {table}.id),
+            url_count = (
+                SELECT COUNT(DISTINCT p.url) FROM {column}_sightings s
+                JOIN pages p ON p.id = s.page_id
+                WHERE s.{column}_id = {table}.id
+            );
+
+        DROP TABLE {table}_v2;",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_fresh_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let applied = migrate(&mut conn).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+
+        // Running again is a no-op
+        assert!(migrate(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_unversioned_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE emails (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                email TEXT NOT NULL,
+                source_url TEXT NOT NULL,
+                found_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(email, source_url)
+            );
+            INSERT INTO emails (email, source_url) VALUES ('a@b.fr', 'https://b.fr');
+            CREATE TABLE url_queue (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL UNIQUE,
+                depth INTEGER NOT NULL,
+                status TEXT DEFAULT 'pending'
+            );",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let emails: u32 = conn.query_row("SELECT COUNT(*) FROM emails", [], |row| row.get(0)).unwrap();
+        assert_eq!(emails, 1);
+        let source: String = conn
+            .query_row(
+                "SELECT p.url FROM email_sightings s JOIN pages p ON p.id = s.page_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(source, "https://b.fr");
+        conn.execute("INSERT INTO runs (seeds, args) VALUES ('[]', '{}')", []).unwrap();
+        conn.execute("INSERT INTO url_queue (run_id, url, depth, priority) VALUES (1, 'https://b.fr', 1, 5)", [])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_queue_is_adopted_by_a_run() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        {
+            let tx = conn.transaction().unwrap();
+            initial_schema(&tx).unwrap();
+            tx.pragma_update(None, "user_version", 1).unwrap();
+            tx.commit().unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO url_queue (url, depth, status) VALUES ('https://b.fr/next', 2, 'pending');
+            INSERT INTO visited (url) VALUES ('https://b.fr/');
+            INSERT INTO crawl_state (key, value) VALUES ('stop_reason', 'max_pages');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let (run_id, stop_reason): (i64, String) = conn
+            .query_row("SELECT id, stop_reason FROM runs", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(stop_reason, "max_pages");
+        let queued: i64 = conn
+            .query_row("SELECT run_id FROM url_queue WHERE url = 'https://b.fr/next'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(queued, run_id);
+        let visited: i64 = conn
+            .query_row("SELECT COUNT(*) FROM visited WHERE run_id = ?1", [run_id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn test_findings_are_normalized_with_counts() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        {
+            let tx = conn.transaction().unwrap();
+            initial_schema(&tx).unwrap();
+            crawl_runs(&tx).unwrap();
+            tx.pragma_update(None, "user_version", 2).unwrap();
+            tx.commit().unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO runs (seeds, args) VALUES ('[]', '{}'), ('[]', '{}');
+            INSERT INTO pages (run_id, url, content_hash) VALUES (1, 'https://a.fr/', 1);
+            INSERT INTO pages (run_id, url, content_hash, duplicate_of) VALUES (1, 'https://a.fr/copy', 1, 'https://a.fr/');
+            INSERT INTO emails (email, source_url, run_id, found_at) VALUES
+                ('x@a.fr', 'https://a.fr/', 1, '2024-01-01 00:00:00'),
+                ('x@a.fr', 'https://a.fr/team', 1, '2024-01-02 00:00:00'),
+                ('x@a.fr', 'https://a.fr/', 2, '2024-02-01 00:00:00');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let (first, last, occurrences, urls): (String, String, u32, u32) = conn
+            .query_row(
+                "SELECT first_seen, last_seen, occurrences, url_count FROM emails WHERE email = 'x@a.fr'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(first, "2024-01-01 00:00:00");
+        assert_eq!(last, "2024-02-01 00:00:00");
+        assert_eq!(occurrences, 3);
+        assert_eq!(urls, 2);
+
+        let duplicate_of: i64 = conn
+            .query_row("SELECT duplicate_of FROM pages WHERE url = 'https://a.fr/copy'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(duplicate_of, 1);
+    }
+
+    #[test]
+    fn test_refuse_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        match migrate(&mut conn) {
+            Err(SchemaError::TooNew { found, supported }) => {
+                assert_eq!(found, latest_version() + 1);
+                assert_eq!(supported, latest_version());
+            }
+            other => panic!("expected TooNew, got {:?}", other.map(|m| m.len())),
+        }
+    }
+}
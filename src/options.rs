@@ -0,0 +1,210 @@
+use crate::budget::HostConfig;
+use crate::extractor::ExtractorConfig;
+use crate::scope::ScopeConfig;
+use crate::strategy::CrawlStrategy;
+use clap::{Args, CommandFactory, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Settings of a crawl, given to [`crate::CrawlerBuilder`]
+///
+/// The command line flattens these into `crawl` and `resume`, and the
+/// configuration file sets them under their snake_case names.
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlOptions {
+    /// Maximum crawl depth (0 = unlimited)
+    #[arg(short, long, default_value_t = 0)]
+    pub depth: u32,
+
+    /// Stay on the same domain only
+    #[arg(short, long, default_value_t = false)]
+    pub stay_on_domain: bool,
+
+    /// Number of async workers
+    #[arg(short, long, default_value_t = 10)]
+    pub workers: usize,
+
+    /// HTTP request timeout in milliseconds
+    #[arg(short = 't', long, default_value_t = 30000)]
+    pub timeout: u64,
+
+    /// Skip page bodies larger than this many bytes (0 = unlimited)
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    pub max_body_size: u64,
+
+    /// Disable SSL certificate verification
+    #[arg(short = 'k', long, default_value_t = false)]
+    pub insecure: bool,
+
+    /// Enable image face detection
+    #[arg(long, default_value_t = false)]
+    pub extract_images: bool,
+
+    /// Path to YOLO face detection model (.pt file)
+    #[arg(long, default_value = "face-detection/yolov12l-face.pt")]
+    pub yolo_model: String,
+
+    /// Minimum image width for face detection
+    #[arg(long, default_value_t = 128)]
+    pub min_image_width: u32,
+
+    /// Minimum image height for face detection
+    #[arg(long, default_value_t = 128)]
+    pub min_image_height: u32,
+
+    /// Output directory for images with faces
+    #[arg(long, default_value = "faces")]
+    pub faces_dir: String,
+
+    /// Maximum URL length before it is treated as a crawl trap (0 = unlimited)
+    #[arg(long, default_value_t = 2048)]
+    pub max_url_length: usize,
+
+    /// Maximum occurrences of a single path segment in a URL (0 = unlimited)
+    #[arg(long, default_value_t = 2)]
+    pub max_repeated_segments: usize,
+
+    /// Maximum distinct query strings queued per path (0 = unlimited)
+    #[arg(long, default_value_t = 100)]
+    pub max_query_variants: usize,
+
+    /// Maximum URLs queued per path pattern, digits generalized (0 = unlimited)
+    #[arg(long, default_value_t = 500)]
+    pub max_pages_per_pattern: usize,
+
+    /// Maximum SimHash distance (in bits) for a page to count as a near-duplicate
+    #[arg(long, default_value_t = 3)]
+    pub simhash_distance: u32,
+
+    /// Stop after crawling this many pages (0 = unlimited)
+    #[arg(long, default_value_t = 0)]
+    pub max_pages: u64,
+
+    /// Stop after this many seconds of crawling, across resumes (0 = unlimited)
+    #[arg(long, default_value_t = 0)]
+    pub max_time: u64,
+
+    /// Stop after downloading this many bytes of pages (0 = unlimited)
+    #[arg(long, default_value_t = 0)]
+    pub max_bytes: u64,
+
+    /// Maximum pages crawled per host (0 = unlimited)
+    #[arg(long, default_value_t = 0)]
+    pub max_pages_per_host: u64,
+
+    /// Crawl order of queued URLs
+    #[arg(long, value_enum, default_value_t = CrawlStrategy::Bfs)]
+    pub strategy: CrawlStrategy,
+
+    /// Keywords boosting URLs in best-first mode (matched in path and anchor text)
+    #[arg(long, value_delimiter = ',', default_value = "contact,mentions-legales,equipe,about,team,impressum")]
+    pub priority_keywords: Vec<String>,
+
+    /// Ignore meta robots, X-Robots-Tag and rel=nofollow (only for sites you own)
+    #[arg(long, default_value_t = false)]
+    pub ignore_robots_meta: bool,
+
+    /// Archive fetched pages as gzipped WARC files in this directory
+    #[arg(long)]
+    pub warc: Option<String>,
+
+    /// Size in MB after which a new WARC file is started (0 = single file)
+    #[arg(long, default_value_t = 1024)]
+    pub warc_max_size: u64,
+
+    /// Keep gzipped page content in the database for `reextract`
+    #[arg(long, default_value_t = false)]
+    pub store_pages: bool,
+
+    /// URLs remembered in memory, about 100 bytes each, to skip database lookups (0 = always ask the database)
+    #[arg(long, default_value_t = 1_000_000)]
+    pub seen_filter_size: usize,
+
+    /// URL patterns to include or exclude (configuration file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub scope: ScopeConfig,
+
+    /// Per-host settings by host name (configuration file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostConfig>,
+
+    /// Findings to extract (configuration file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub extractor: ExtractorConfig,
+}
+
+impl Default for CrawlOptions {
+    /// The command line defaults
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[command(flatten)]
+            options: CrawlOptions,
+        }
+        let matches = Defaults::command().get_matches_from(["couscous-crawler"]);
+        Defaults::from_arg_matches(&matches).expect("crawl options have defaults").options
+    }
+}
+
+impl CrawlOptions {
+    /// The options a run was started with, from their recorded JSON
+    ///
+    /// Options the run didn't record, or this version no longer accepts, keep
+    /// their defaults.
+    pub fn from_recorded(recorded: &str) -> Self {
+        let defaults = CrawlOptions::default();
+        let Ok(Value::Object(recorded)) = serde_json::from_str(recorded) else {
+            return defaults;
+        };
+        let Ok(Value::Object(mut merged)) = serde_json::to_value(&defaults) else {
+            unreachable!("crawl options serialize to an object");
+        };
+
+        // Runs started before the options were split from the rest recorded every setting
+        for (key, value) in recorded {
+            if !merged.contains_key(&key) {
+                continue;
+            }
+            let previous = merged.insert(key.clone(), value);
+            if serde_json::from_value::<CrawlOptions>(Value::Object(merged.clone())).is_err() {
+                warn!(setting = %key, "Ignoring recorded option of the run");
+                if let Some(previous) = previous {
+                    merged.insert(key, previous);
+                }
+            }
+        }
+
+        serde_json::from_value(Value::Object(merged)).unwrap_or(defaults)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_command_line() {
+        let options = CrawlOptions::default();
+        assert_eq!((options.workers, options.depth, options.timeout), (10, 0, 30000));
+        assert_eq!(options.strategy, CrawlStrategy::Bfs);
+        assert!(options.extractor.emails);
+        assert!(options.priority_keywords.contains(&"contact".to_string()));
+    }
+
+    #[test]
+    fn test_from_recorded() {
+        let recorded = r#"{"depth":3,"workers":"many","db":"emails.db","extractor":{"phones":false}}"#;
+        let options = CrawlOptions::from_recorded(recorded);
+        assert_eq!(options.depth, 3);
+        assert_eq!(options.workers, 10);
+        assert!(!options.extractor.phones);
+        assert!(options.extractor.emails);
+
+        assert_eq!(CrawlOptions::from_recorded("not json").depth, 0);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::stats::{CrawlStats, StatsSnapshot};
+use crate::storage::{self, Storage};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::info;
+
+/// Refresh period of the live display
+const LIVE_INTERVAL: Duration = Duration::from_millis(500);
+/// Period of the status log lines when stdout is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// Workers given their own line in the live display
+const MAX_WORKER_LINES: usize = 16;
+
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Live display area on stdout, shared with the log writer
+fn multi() -> &'static MultiProgress {
+    MULTI.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+}
+
+/// Log output to stderr that hides the live display while a line is written
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match MULTI.get() {
+            Some(multi) => multi.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Crawl status shown while the workers run
+///
+/// On a terminal this is a live area with the crawl counters and the URL of
+/// each worker, otherwise a status line is logged periodically.
+pub struct ProgressDisplay {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl ProgressDisplay {
+    pub fn start(stats: Arc<CrawlStats>, db: Arc<dyn Storage>) -> Self {
+        let (stop, stopped) = watch::channel(false);
+        let task = if io::stdout().is_terminal() {
+            tokio::spawn(live(stats, db, stopped))
+        } else {
+            tokio::spawn(log_lines(stats, db, stopped))
+        };
+        ProgressDisplay { stop, task }
+    }
+
+    /// Stop the display and clear the live area
+    pub async fn finish(self) {
+        let _ = self.stop.send(true);
+        let _ = self.task.await;
+    }
+}
+
+async fn live(stats: Arc<CrawlStats>, db: Arc<dyn Storage>, mut stopped: watch::Receiver<bool>) {
+    let multi = multi();
+    let status = multi.add(ProgressBar::new_spinner());
+    status.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] {msg}").unwrap());
+
+    let worker_style = ProgressStyle::with_template("  {prefix:.dim} {wide_msg}").unwrap();
+    let shown = stats.workers().min(MAX_WORKER_LINES);
+    let mut lines: Vec<ProgressBar> = (0..shown)
+        .map(|id| {
+            let line = multi.add(ProgressBar::new_spinner());
+            line.set_style(worker_style.clone());
+            line.set_prefix(format!("worker {:>2}", id));
+            line
+        })
+        .collect();
+    if stats.workers() > shown {
+        let line = multi.add(ProgressBar::new_spinner());
+        line.set_style(ProgressStyle::with_template("  {msg:.dim}").unwrap());
+        line.set_message(format!("and {} more workers", stats.workers() - shown));
+        lines.push(line);
+    }
+
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(LIVE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stopped.changed() => break,
+        }
+        let queued = queued(&db).await;
+        status.set_message(status_line(&stats.snapshot(), started.elapsed(), queued));
+        status.tick();
+        for (id, line) in lines.iter().take(shown).enumerate() {
+            line.set_message(stats.current(id).unwrap_or_else(|| "idle".to_string()));
+        }
+    }
+
+    status.finish_and_clear();
+    for line in lines {
+        line.finish_and_clear();
+    }
+}
+
+async fn log_lines(stats: Arc<CrawlStats>, db: Arc<dyn Storage>, mut stopped: watch::Receiver<bool>) {
+    let started = Instant::now();
+    let mut interval = tokio::time::interval_at(started + LOG_INTERVAL, LOG_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stopped.changed() => break,
+        }
+        let queued = queued(&db).await;
+        let stats = stats.snapshot();
+        info!(
+            pages = stats.pages,
+            pages_per_second = rate(stats.pages, started.elapsed()),
+            queued,
+            in_flight = stats.in_flight,
+            emails = stats.emails,
+            phones = stats.phones,
+            images = stats.images,
+            errors = stats.errors,
+            "Progress"
+        );
+    }
+}
+
+/// Pending URLs of the run, `None` if the storage can't tell
+async fn queued(db: &Arc<dyn Storage>) -> Option<u64> {
+    storage::call(db, |db| db.pending_count()).await.ok()
+}
+
+/// Pages per second, to one decimal
+fn rate(pages: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return 0.0;
+    }
+    (pages as f64 / seconds * 10.0).round() / 10.0
+}
+
+fn status_line(stats: &StatsSnapshot, elapsed: Duration, queued: Option<u64>) -> String {
+    let queued = queued.map_or("?".to_string(), |queued| queued.to_string());
+    format!(
+        "{} pages ({}/s) | {} queued | {} fetching | {} emails, {} phones, {} faces | {} errors",
+        stats.pages,
+        rate(stats.pages, elapsed),
+        queued,
+        stats.in_flight,
+        stats.emails,
+        stats.phones,
+        stats.images,
+        stats.errors,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line() {
+        let stats = StatsSnapshot { pages: 25, in_flight: 3, emails: 4, phones: 1, images: 0, errors: 2 };
+        assert_eq!(
+            status_line(&stats, Duration::from_secs(10), Some(120)),
+            "25 pages (2.5/s) | 120 queued | 3 fetching | 4 emails, 1 phones, 0 faces | 2 errors"
+        );
+        assert!(status_line(&stats, Duration::ZERO, None).starts_with("25 pages (0/s) | ? queued"));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::crawler::decode_body;
+use crate::database::Database;
+use crate::options::CrawlOptions;
+use crate::robots::RobotsDirectives;
+use crate::warc::{parse_response, warc_files, WarcReader};
+
+/// Where the pages to re-extract are read from
+pub enum PageSource<'a> {
+    /// The run's page store (`--store-pages`)
+    Database,
+    /// WARC files or directories written with `--warc`
+    Warc(&'a [String]),
+}
+
+/// A finding added to or removed from a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FindingChange {
+    /// `email` or `phone`
+    pub kind: &'static str,
+    pub value: String,
+    pub url: String,
+    pub added: bool,
+}
+
+/// Findings added and removed by a re-extraction
+#[derive(Debug, Default)]
+pub struct ReextractReport {
+    pub changes: Vec<FindingChange>,
+    pub pages: u64,
+    pub skipped: u64,
+    pub emails_added: u64,
+    pub emails_removed: u64,
+    pub phones_added: u64,
+    pub phones_removed: u64,
+}
+
+/// Run the current extractors over the stored pages of the run
+///
+/// `options` are those the run was crawled with, see [`CrawlOptions::from_recorded`],
+/// so findings the crawl left out are left out again. With `dry_run` the
+/// changes are only reported.
+pub fn reextract(
+    db: &Database,
+    options: &CrawlOptions,
+    source: PageSource,
+    dry_run: bool,
+) -> Result<ReextractReport, Box<dyn std::error::Error>> {
+    let mut report = ReextractReport::default();
+
+    match source {
+        PageSource::Database => {
+            for url in db.get_stored_pages()? {
+                if let Some(html) = db.get_page_content(&url)? {
+                    apply(db, options, &url, &html, dry_run, &mut report)?;
+                }
+            }
+        }
+        PageSource::Warc(paths) => {
+            for path in paths {
+                for file in warc_files(path)? {
+                    let mut reader = WarcReader::open(&file)?;
+                    while let Some(record) = reader.next_record()? {
+                        if record.warc_type != "response" {
+                            continue;
+                        }
+                        let Some(url) = record.target_uri else {
+                            continue;
+                        };
+                        let Some((headers, body)) = parse_response(&record.block) else {
+                            report.skipped += 1;
+                            continue;
+                        };
+
+                        // Only pages of this run that allowed indexing had findings,
+                        // unless the run ignored robots directives
+                        let html = decode_body(&headers, body);
+                        let robots = if options.ignore_robots_meta {
+                            RobotsDirectives::default()
+                        } else {
+                            headers
+                                .get_all("x-robots-tag")
+                                .iter()
+                                .filter_map(|value| value.to_str().ok())
+                                .map(RobotsDirectives::from_header)
+                                .fold(RobotsDirectives::from_html(&html), RobotsDirectives::merge)
+                        };
+                        if robots.noindex || !db.has_page(&url)? {
+                            report.skipped += 1;
+                            continue;
+                        }
+
+                        apply(db, options, &url, &html, dry_run, &mut report)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if !dry_run {
+        db.refresh_run_counts()?;
+    }
+    Ok(report)
+}
+
+/// Compare a page's stored findings with a fresh extraction and apply the difference
+fn apply(
+    db: &Database,
+    options: &CrawlOptions,
+    url: &str,
+    html: &str,
+    dry_run: bool,
+    report: &mut ReextractReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    report.pages += 1;
+    let (old_emails, old_phones) = db.get_page_findings(url)?;
+
+    let (added, removed) = diff(&old_emails, options.extractor.emails_in(html));
+    report.add_changes("email", url, &added, &removed);
+    report.emails_added += added.len() as u64;
+    report.emails_removed += removed.len() as u64;
+    if !dry_run {
+        db.insert_emails(added, url)?;
+        db.remove_emails(removed, url)?;
+    }
+
+    let (added, removed) = diff(&old_phones, options.extractor.phones_in(html));
+    report.add_changes("phone", url, &added, &removed);
+    report.phones_added += added.len() as u64;
+    report.phones_removed += removed.len() as u64;
+    if !dry_run {
+        db.insert_phones(added, url)?;
+        db.remove_phones(removed, url)?;
+    }
+
+    Ok(())
+}
+
+/// Values found now but not before, and values found before but not now
+fn diff(old: &[String], mut new: Vec<String>) -> (Vec<String>, Vec<String>) {
+    new.sort();
+    new.dedup();
+    let removed = old.iter().filter(|value| !new.contains(value)).cloned().collect();
+    let added = new.into_iter().filter(|value| !old.contains(value)).collect();
+    (added, removed)
+}
+
+impl ReextractReport {
+    fn add_changes(&mut self, kind: &'static str, url: &str, added: &[String], removed: &[String]) {
+        let change = |value: &String, added| FindingChange { kind, value: value.clone(), url: url.to_string(), added };
+        self.changes.extend(added.iter().map(|value| change(value, true)));
+        self.changes.extend(removed.iter().map(|value| change(value, false)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDb;
+
+    #[test]
+    fn test_diff() {
+        let old = vec!["a@x.fr".to_string(), "b@x.fr".to_string()];
+        let new = vec!["c@x.fr".to_string(), "a@x.fr".to_string(), "c@x.fr".to_string()];
+
+        let (added, removed) = diff(&old, new);
+        assert_eq!(added, vec!["c@x.fr".to_string()]);
+        assert_eq!(removed, vec!["b@x.fr".to_string()]);
+    }
+
+    #[test]
+    fn test_recorded_extractor_settings() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let recorded = r#"{"extractor":{"phones":false,"ignore_email_domains":["example.com"]}}"#;
+        let db = db.for_run(db.begin_run("[\"https://a.fr/\"]", recorded).unwrap());
+        db.insert_page("https://a.fr/", Some(200), None, None).unwrap();
+        db.store_page_content("https://a.fr/", "<p>info@example.com sales@a.fr +33 1 23 45 67 89</p>").unwrap();
+
+        // The crawl left out the example.com email and the phone, so does the re-extraction
+        let options = CrawlOptions::from_recorded(recorded);
+        let report = reextract(&db, &options, PageSource::Database, false).unwrap();
+        assert_eq!((report.emails_added, report.phones_added), (1, 0));
+        let added = FindingChange { kind: "email", value: "sales@a.fr".to_string(), url: "https://a.fr/".to_string(), added: true };
+        assert_eq!(report.changes, vec![added]);
+        assert_eq!(db.get_page_findings("https://a.fr/").unwrap().0, vec!["sales@a.fr".to_string()]);
+    }
+}
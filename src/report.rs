@@ -0,0 +1,377 @@
+use clap::ValueEnum;
+use colored::*;
+use rusqlite::{named_params, Connection, Result};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use url::Url;
+
+/// Number of buckets the crawl's time span is split into for throughput
+const THROUGHPUT_BUCKETS: i64 = 24;
+
+/// How the report is printed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportFormat {
+    /// Terminal tables
+    Table,
+    Json,
+}
+
+/// Breakdown of what a crawl fetched and found
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Run the report covers, all runs if unset
+    pub run: Option<i64>,
+    pub pages: u64,
+    /// Hosts with the most pages
+    pub hosts: Vec<HostStats>,
+    pub status_codes: Vec<StatusStats>,
+    /// Recorded crawl errors by kind
+    pub errors: Vec<ErrorStats>,
+    /// Domains with the most distinct emails
+    pub email_domains: Vec<DomainStats>,
+    /// Pages with the most findings
+    pub top_pages: Vec<PageStats>,
+    pub throughput: Vec<ThroughputBucket>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct HostStats {
+    pub host: String,
+    pub pages: u64,
+    /// Pages answered with a 4xx or 5xx status
+    pub errors: u64,
+    pub emails: u64,
+    pub phones: u64,
+    pub images: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StatusStats {
+    /// HTTP status, unknown for pages fetched before it was recorded
+    pub status: Option<u16>,
+    pub pages: u64,
+    /// Share of all pages
+    pub rate: f64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ErrorStats {
+    pub kind: String,
+    pub errors: u64,
+    /// Distinct URLs that failed
+    pub urls: u64,
+    /// Errors not yet retried that `resume --retry-errors` would requeue
+    pub retryable: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DomainStats {
+    pub domain: String,
+    pub emails: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PageStats {
+    pub findings: u64,
+    pub url: String,
+    pub emails: u64,
+    pub phones: u64,
+    pub images: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ThroughputBucket {
+    /// Start of the bucket (UTC)
+    pub start: String,
+    pub pages: u64,
+    pub pages_per_minute: f64,
+}
+
+/// Build the report of a run (or all runs), keeping `limit` rows per ranking
+///
+/// Pages are scanned once, so memory stays bounded by the number of hosts.
+pub fn build_report(conn: &Connection, run: Option<i64>, limit: usize) -> Result<Report> {
+    let mut hosts: HashMap<String, HostStats> = HashMap::new();
+    let mut statuses: HashMap<Option<u16>, u64> = HashMap::new();
+    let mut top_pages = BinaryHeap::new();
+    let mut pages = 0;
+
+    let mut stmt = conn.prepare(
+        "SELECT p.url, p.status,
+             (SELECT COUNT(*) FROM email_sightings s WHERE s.page_id = p.id),
+             (SELECT COUNT(*) FROM phone_sightings s WHERE s.page_id = p.id),
+             (SELECT COUNT(*) FROM images i WHERE i.page_id = p.id)
+         FROM pages p
+         WHERE :run IS NULL OR p.run_id = :run",
+    )?;
+    let mut rows = stmt.query(named_params! { ":run": run })?;
+    while let Some(row) = rows.next()? {
+        let url: String = row.get(0)?;
+        let status: Option<u16> = row.get(1)?;
+        let (emails, phones, images): (u64, u64, u64) = (row.get(2)?, row.get(3)?, row.get(4)?);
+        pages += 1;
+        *statuses.entry(status).or_default() += 1;
+
+        let host = Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let stats = hosts.entry(host.clone()).or_insert_with(|| HostStats { host, ..Default::default() });
+        stats.pages += 1;
+        stats.errors += status.is_some_and(|status| status >= 400) as u64;
+        stats.emails += emails;
+        stats.phones += phones;
+        stats.images += images;
+
+        // Keep the `limit` pages with the most findings in a min-heap
+        let findings = emails + phones + images;
+        if findings > 0 && limit > 0 {
+            top_pages.push(Reverse(PageStats { findings, url, emails, phones, images }));
+            if top_pages.len() > limit {
+                top_pages.pop();
+            }
+        }
+    }
+
+    let mut hosts: Vec<HostStats> = hosts.into_values().collect();
+    hosts.sort_by(|a, b| b.pages.cmp(&a.pages).then_with(|| a.host.cmp(&b.host)));
+    hosts.truncate(limit);
+
+    let mut status_codes: Vec<StatusStats> = statuses
+        .into_iter()
+        .map(|(status, count)| StatusStats { status, pages: count, rate: count as f64 / pages as f64 })
+        .collect();
+    // Known codes in order, unknown last
+    status_codes.sort_by_key(|stats| (stats.status.is_none(), stats.status));
+
+    let mut top_pages: Vec<PageStats> = top_pages.into_iter().map(|Reverse(page)| page).collect();
+    top_pages.sort_by(|a, b| b.findings.cmp(&a.findings).then_with(|| a.url.cmp(&b.url)));
+
+    Ok(Report {
+        run,
+        pages,
+        hosts,
+        status_codes,
+        errors: errors(conn, run)?,
+        email_domains: email_domains(conn, run, limit)?,
+        top_pages,
+        throughput: throughput(conn, run)?,
+    })
+}
+
+/// Recorded errors by kind, the most frequent first
+fn errors(conn: &Connection, run: Option<i64>) -> Result<Vec<ErrorStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT kind, COUNT(*) AS errors, COUNT(DISTINCT url), SUM(retryable AND NOT retried)
+         FROM errors
+         WHERE :run IS NULL OR run_id = :run
+         GROUP BY kind
+         ORDER BY errors DESC, kind",
+    )?;
+    let rows = stmt.query_map(named_params! { ":run": run }, |row| {
+        Ok(ErrorStats { kind: row.get(0)?, errors: row.get(1)?, urls: row.get(2)?, retryable: row.get(3)? })
+    })?;
+    rows.collect()
+}
+
+/// Domains with the most distinct emails
+fn email_domains(conn: &Connection, run: Option<i64>, limit: usize) -> Result<Vec<DomainStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT lower(substr(e.email, instr(e.email, '@') + 1)) AS domain, COUNT(*) AS emails
+         FROM emails e
+         WHERE :run IS NULL OR EXISTS (
+             SELECT 1 FROM email_sightings s JOIN pages p ON p.id = s.page_id
+             WHERE s.email_id = e.id AND p.run_id = :run
+         )
+         GROUP BY domain
+         ORDER BY emails DESC, domain
+         LIMIT :limit",
+    )?;
+    let rows = stmt.query_map(named_params! { ":run": run, ":limit": limit as i64 }, |row| {
+        Ok(DomainStats { domain: row.get(0)?, emails: row.get(1)? })
+    })?;
+    rows.collect()
+}
+
+/// Pages fetched per time bucket, the span is split into about `THROUGHPUT_BUCKETS`
+/// buckets of whole minutes
+fn throughput(conn: &Connection, run: Option<i64>) -> Result<Vec<ThroughputBucket>> {
+    let (first, last): (Option<i64>, Option<i64>) = conn.query_row(
+        "SELECT MIN(CAST(strftime('%s', fetched_at) AS INTEGER)), MAX(CAST(strftime('%s', fetched_at) AS INTEGER))
+         FROM pages WHERE :run IS NULL OR run_id = :run",
+        named_params! { ":run": run },
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok(Vec::new());
+    };
+    let minutes = ((last - first) / 60 / THROUGHPUT_BUCKETS).max(1);
+    let bucket = minutes * 60;
+
+    let mut stmt = conn.prepare(
+        "SELECT datetime(CAST(strftime('%s', fetched_at) AS INTEGER) / :bucket * :bucket, 'unixepoch') AS start,
+             COUNT(*)
+         FROM pages
+         WHERE (:run IS NULL OR run_id = :run) AND fetched_at IS NOT NULL
+         GROUP BY start
+         ORDER BY start",
+    )?;
+    let rows = stmt.query_map(named_params! { ":run": run, ":bucket": bucket }, |row| {
+        let pages: u64 = row.get(1)?;
+        Ok(ThroughputBucket { start: row.get(0)?, pages, pages_per_minute: pages as f64 / minutes as f64 })
+    })?;
+    rows.collect()
+}
+
+/// Print the report as terminal tables
+pub fn print_report(report: &Report) {
+    match report.run {
+        Some(run) => println!("{}", format!("Report for run #{} ({} pages)", run, report.pages).bold()),
+        None => println!("{}", format!("Report for all runs ({} pages)", report.pages).bold()),
+    }
+
+    print_table(
+        "Hosts",
+        &["host", "pages", "errors", "emails", "phones", "images"],
+        report
+            .hosts
+            .iter()
+            .map(|h| {
+                vec![
+                    h.host.clone(),
+                    h.pages.to_string(),
+                    h.errors.to_string(),
+                    h.emails.to_string(),
+                    h.phones.to_string(),
+                    h.images.to_string(),
+                ]
+            })
+            .collect(),
+    );
+    print_table(
+        "Status codes",
+        &["status", "pages", "rate"],
+        report
+            .status_codes
+            .iter()
+            .map(|s| {
+                let status = s.status.map_or("unknown".to_string(), |status| status.to_string());
+                vec![status, s.pages.to_string(), format!("{:.1}%", s.rate * 100.0)]
+            })
+            .collect(),
+    );
+    print_table(
+        "Errors",
+        &["kind", "errors", "urls", "retryable"],
+        report
+            .errors
+            .iter()
+            .map(|e| vec![e.kind.clone(), e.errors.to_string(), e.urls.to_string(), e.retryable.to_string()])
+            .collect(),
+    );
+    print_table(
+        "Top email domains",
+        &["domain", "emails"],
+        report.email_domains.iter().map(|d| vec![d.domain.clone(), d.emails.to_string()]).collect(),
+    );
+    print_table(
+        "Pages with the most findings",
+        &["url", "emails", "phones", "images"],
+        report
+            .top_pages
+            .iter()
+            .map(|p| vec![p.url.clone(), p.emails.to_string(), p.phones.to_string(), p.images.to_string()])
+            .collect(),
+    );
+    print_table(
+        "Throughput",
+        &["start (UTC)", "pages", "pages/min"],
+        report
+            .throughput
+            .iter()
+            .map(|t| vec![t.start.clone(), t.pages.to_string(), format!("{:.1}", t.pages_per_minute)])
+            .collect(),
+    );
+}
+
+/// Print a titled table, the first column left-aligned and the others right-aligned
+fn print_table(title: &str, headers: &[&str], rows: Vec<Vec<String>>) {
+    println!();
+    println!("{}", title.cyan().bold());
+    if rows.is_empty() {
+        println!("  (none)");
+        return;
+    }
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| if i == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+
+    let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    println!("  {}", format_row(&headers).bold());
+    for row in &rows {
+        println!("  {}", format_row(row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, TempDb};
+    use crate::error::CrawlError;
+
+    #[test]
+    fn test_build_report() {
+        let temp = TempDb::new();
+        let db = temp.open();
+        let first = db.for_run(db.begin_run("[\"https://a.fr/\"]", "{}").unwrap());
+        first.insert_page("https://a.fr/", Some(200), None, None).unwrap();
+        first.insert_page("https://a.fr/missing", Some(404), None, None).unwrap();
+        first.insert_page("https://b.fr/", Some(200), None, None).unwrap();
+        first.insert_emails(vec!["x@a.fr".to_string(), "y@a.fr".to_string()], "https://a.fr/").unwrap();
+        first.insert_emails(vec!["z@b.fr".to_string()], "https://b.fr/").unwrap();
+        first.insert_phones(vec!["0102030405".to_string()], "https://b.fr/").unwrap();
+        first.record_error("https://a.fr/slow", &CrawlError::Timeout).unwrap();
+        first.record_error("https://a.fr/slow", &CrawlError::Timeout).unwrap();
+        first.record_error("https://a.fr/", &CrawlError::HttpStatus(503)).unwrap();
+        let second = db.for_run(db.begin_run("[\"https://c.fr/\"]", "{}").unwrap());
+        second.insert_emails(vec!["w@c.fr".to_string()], "https://c.fr/").unwrap();
+        drop((db, first, second));
+
+        let conn = Database::open_read_only(temp.path()).unwrap();
+        let report = build_report(&conn, Some(1), 1).unwrap();
+
+        assert_eq!(report.pages, 3);
+        let host = HostStats { host: "a.fr".to_string(), pages: 2, errors: 1, emails: 2, phones: 0, images: 0 };
+        assert_eq!(report.hosts, vec![host]);
+        assert_eq!(report.status_codes.len(), 2);
+        assert_eq!((report.status_codes[1].status, report.status_codes[1].pages), (Some(404), 1));
+        let timeouts = ErrorStats { kind: "timeout".to_string(), errors: 2, urls: 1, retryable: 2 };
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0], timeouts);
+        assert_eq!(report.email_domains, vec![DomainStats { domain: "a.fr".to_string(), emails: 2 }]);
+        assert_eq!(report.top_pages.len(), 1);
+        assert_eq!(report.top_pages[0].findings, 2);
+        assert_eq!(report.throughput.iter().map(|t| t.pages).sum::<u64>(), 3);
+
+        // All runs include the second run's page, whose status was never recorded
+        let report = build_report(&conn, None, 10).unwrap();
+        assert_eq!(report.pages, 4);
+        assert_eq!(report.status_codes.last().unwrap().status, None);
+        assert_eq!(report.email_domains.len(), 3);
+    }
+}
@@ -0,0 +1,127 @@
+use scraper::{Html, Selector};
+
+/// Indexing directives from `<meta name="robots">` and `X-Robots-Tag`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RobotsDirectives {
+    /// Do not store findings from the page
+    pub noindex: bool,
+    /// Do not follow links from the page
+    pub nofollow: bool,
+    /// Do not archive the page content
+    pub noarchive: bool,
+}
+
+impl RobotsDirectives {
+    /// Parse a comma separated directive list such as `noindex, nofollow`
+    pub fn parse(content: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for token in content.split(',') {
+            match token.trim().to_lowercase().as_str() {
+                "noindex" => directives.noindex = true,
+                "nofollow" => directives.nofollow = true,
+                "noarchive" => directives.noarchive = true,
+                "none" => {
+                    directives.noindex = true;
+                    directives.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+        directives
+    }
+
+    /// Parse an `X-Robots-Tag` header value
+    ///
+    /// Values scoped to a named crawler (`googlebot: noindex`) do not apply to us.
+    pub fn from_header(value: &str) -> Self {
+        match value.split_once(':') {
+            Some((agent, rest)) if !agent.contains(',') && !is_directive(agent) => {
+                if agent.trim() == "*" {
+                    RobotsDirectives::parse(rest)
+                } else {
+                    RobotsDirectives::default()
+                }
+            }
+            _ => RobotsDirectives::parse(value),
+        }
+    }
+
+    /// Collect directives from the robots meta tags of an HTML document
+    pub fn from_html(html: &str) -> Self {
+        let document = Html::parse_document(html);
+        let selector = Selector::parse("meta[name][content]").unwrap();
+        let mut directives = RobotsDirectives::default();
+
+        for element in document.select(&selector) {
+            let name = element.value().attr("name").unwrap_or("");
+            if name.eq_ignore_ascii_case("robots") {
+                let content = element.value().attr("content").unwrap_or("");
+                directives = directives.merge(RobotsDirectives::parse(content));
+            }
+        }
+
+        directives
+    }
+
+    /// Combine two sets of directives, the most restrictive wins
+    pub fn merge(self, other: RobotsDirectives) -> Self {
+        RobotsDirectives {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+            noarchive: self.noarchive || other.noarchive,
+        }
+    }
+}
+
+/// Whether the text before a colon is a directive (e.g. `unavailable_after: ...`)
+fn is_directive(token: &str) -> bool {
+    matches!(
+        token.trim().to_lowercase().as_str(),
+        "unavailable_after" | "max-snippet" | "max-image-preview" | "max-video-preview"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directives() {
+        let directives = RobotsDirectives::parse("NoIndex, nofollow");
+        assert!(directives.noindex);
+        assert!(directives.nofollow);
+        assert!(!directives.noarchive);
+
+        assert_eq!(
+            RobotsDirectives::parse("none"),
+            RobotsDirectives { noindex: true, nofollow: true, noarchive: false }
+        );
+        assert_eq!(RobotsDirectives::parse("all"), RobotsDirectives::default());
+    }
+
+    #[test]
+    fn test_from_header() {
+        assert!(RobotsDirectives::from_header("noindex").noindex);
+        assert!(RobotsDirectives::from_header("*: nofollow").nofollow);
+        assert_eq!(RobotsDirectives::from_header("googlebot: noindex"), RobotsDirectives::default());
+        assert!(RobotsDirectives::from_header("noarchive, unavailable_after: 2030-01-01").noarchive);
+    }
+
+    #[test]
+    fn test_from_html() {
+        let html = r#"
+            <html>
+                <head>
+                    <meta name="description" content="noindex">
+                    <meta name="ROBOTS" content="noindex">
+                    <meta name="robots" content="noarchive">
+                </head>
+            </html>
+        "#;
+
+        let directives = RobotsDirectives::from_html(html);
+        assert!(directives.noindex);
+        assert!(directives.noarchive);
+        assert!(!directives.nofollow);
+    }
+}
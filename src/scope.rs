@@ -0,0 +1,59 @@
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// URL patterns limiting what gets queued (config file `[scope]`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScopeConfig {
+    /// Only queue URLs matching one of these regexes (all if empty)
+    pub include: Vec<String>,
+    /// Never queue URLs matching one of these regexes
+    pub exclude: Vec<String>,
+}
+
+/// Compiled scope rules
+pub struct Scope {
+    include: RegexSet,
+    exclude: RegexSet,
+}
+
+impl Scope {
+    pub fn new(config: &ScopeConfig) -> Result<Self, regex::Error> {
+        Ok(Scope {
+            include: RegexSet::new(&config.include)?,
+            exclude: RegexSet::new(&config.exclude)?,
+        })
+    }
+
+    /// Whether a link may be queued
+    pub fn allows(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        (self.include.is_empty() || self.include.is_match(url)) && !self.exclude.is_match(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_and_exclude() {
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        let scope = Scope::new(&ScopeConfig::default()).unwrap();
+        assert!(scope.allows(&url("https://a.fr/anything")));
+
+        let scope = Scope::new(&ScopeConfig {
+            include: vec!["^https://a\\.fr/".to_string()],
+            exclude: vec!["/tag/".to_string(), "\\.pdf$".to_string()],
+        })
+        .unwrap();
+        assert!(scope.allows(&url("https://a.fr/contact")));
+        assert!(!scope.allows(&url("https://b.fr/contact")));
+        assert!(!scope.allows(&url("https://a.fr/tag/news")));
+        assert!(!scope.allows(&url("https://a.fr/doc.pdf")));
+
+        assert!(Scope::new(&ScopeConfig { include: vec!["(".to_string()], ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Number of independently locked shards
+const SHARDS: usize = 16;
+
+/// In-memory set of URLs already queued or visited in the current run
+///
+/// Keeps the URLs themselves, so its answers are exact and most lookups never
+/// reach the storage. Once `capacity` URLs are held no more are added, and
+/// lookups that miss must be confirmed with the storage
+/// ([`SeenFilter::is_complete`] turns false).
+pub struct SeenFilter {
+    capacity: usize,
+    len: AtomicUsize,
+    complete: AtomicBool,
+    shards: Vec<Mutex<Shard>>,
+}
+
+#[derive(Default)]
+struct Shard {
+    // Queued or visited URLs, and whether they were visited
+    urls: HashMap<Box<str>, bool>,
+}
+
+impl SeenFilter {
+    /// Create an empty filter holding up to `capacity` URLs (0 disables it)
+    pub fn new(capacity: usize) -> Self {
+        SeenFilter {
+            capacity,
+            len: AtomicUsize::new(0),
+            complete: AtomicBool::new(capacity > 0),
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::default())).collect(),
+        }
+    }
+
+    /// Whether every queued and visited URL is in the filter, so misses are definite
+    pub fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::SeqCst)
+    }
+
+    /// Record a URL as seen, returns `Some(true)` if it was new and `Some(false)`
+    /// if already seen, `None` when the filter is full and the storage must decide
+    pub fn claim(&self, url: &str) -> Option<bool> {
+        let mut shard = self.shard(url);
+        if shard.urls.contains_key(url) {
+            return Some(false);
+        }
+        if !self.reserve() {
+            return None;
+        }
+        shard.urls.insert(url.into(), false);
+        Some(true)
+    }
+
+    /// Forget a claimed URL that couldn't be queued, visited URLs are kept
+    pub fn release(&self, url: &str) {
+        let mut shard = self.shard(url);
+        if shard.urls.get(url) == Some(&false) {
+            shard.urls.remove(url);
+            self.len.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Record a URL as visited
+    pub fn mark_visited(&self, url: &str) {
+        let mut shard = self.shard(url);
+        if let Some(visited) = shard.urls.get_mut(url) {
+            *visited = true;
+        } else if self.reserve() {
+            shard.urls.insert(url.into(), true);
+        }
+    }
+
+    /// Whether a URL was visited, `None` when the filter can't tell
+    pub fn is_visited(&self, url: &str) -> Option<bool> {
+        if self.shard(url).urls.get(url) == Some(&true) {
+            return Some(true);
+        }
+        self.is_complete().then_some(false)
+    }
+
+    /// Number of URLs held
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    /// Take a slot for a new URL, marking the filter incomplete once full
+    fn reserve(&self) -> bool {
+        let reserved = self
+            .len
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |len| (len < self.capacity).then_some(len + 1))
+            .is_ok();
+        if !reserved {
+            self.complete.store(false, Ordering::SeqCst);
+        }
+        reserved
+    }
+
+    fn shard(&self, url: &str) -> std::sync::MutexGuard<'_, Shard> {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        self.shards[hasher.finish() as usize % SHARDS].lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_and_visit() {
+        let seen = SeenFilter::new(10);
+        assert_eq!(seen.claim("https://a.fr/"), Some(true));
+        assert_eq!(seen.claim("https://a.fr/"), Some(false));
+        assert_eq!(seen.is_visited("https://a.fr/"), Some(false));
+
+        seen.mark_visited("https://a.fr/");
+        assert_eq!(seen.is_visited("https://a.fr/"), Some(true));
+        assert_eq!(seen.len(), 1);
+
+        // Visiting an unqueued URL also makes it known
+        seen.mark_visited("https://a.fr/other");
+        assert_eq!(seen.claim("https://a.fr/other"), Some(false));
+        assert!(seen.is_complete());
+
+        // A released claim can be made again, a visit is kept
+        assert_eq!(seen.claim("https://a.fr/new"), Some(true));
+        seen.release("https://a.fr/new");
+        seen.release("https://a.fr/other");
+        assert_eq!(seen.claim("https://a.fr/new"), Some(true));
+        assert_eq!(seen.is_visited("https://a.fr/other"), Some(true));
+        assert_eq!(seen.len(), 3);
+    }
+
+    #[test]
+    fn test_full_filter_defers_to_storage() {
+        let seen = SeenFilter::new(1);
+        assert_eq!(seen.claim("https://a.fr/"), Some(true));
+        assert_eq!(seen.claim("https://a.fr/new"), None);
+        assert!(!seen.is_complete());
+
+        // Hits are still answered, misses are not
+        assert_eq!(seen.claim("https://a.fr/"), Some(false));
+        assert_eq!(seen.is_visited("https://a.fr/"), None);
+
+        let disabled = SeenFilter::new(0);
+        assert_eq!(disabled.claim("https://a.fr/"), None);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::error::CrawlError;
+use crate::events::{CrawlEvent, EventStream};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::Instant;
+use tracing::{debug, error, warn};
+
+/// Write each finding of a crawl as a JSON line, returns how many were written
+///
+/// Lines are flushed as they are written so a reader like `jq` sees them live.
+pub async fn write_ndjson<W: AsyncWrite + Unpin>(mut events: EventStream, mut out: W) -> std::io::Result<u64> {
+    let mut written = 0;
+    while let Some(event) = events.recv().await {
+        if !event.is_finding() {
+            continue;
+        }
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        out.write_all(&line).await?;
+        out.flush().await?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Delivery counts of a webhook
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WebhookStats {
+    /// Findings delivered
+    pub sent: u64,
+    pub batches: u64,
+    /// Findings given up on after the retries, refused by the endpoint, or
+    /// dropped because it fell too far behind
+    pub dropped: u64,
+}
+
+/// POSTs findings in batches to an HTTP endpoint, as a JSON array of events
+///
+/// Batches are delivered in the background so a slow or unreachable endpoint
+/// never holds the crawl back: once `queue_size` batches wait for delivery,
+/// new ones are dropped.
+pub struct WebhookSink {
+    client: Client,
+    url: String,
+    batch_size: usize,
+    flush_interval: Duration,
+    queue_size: usize,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<Self, CrawlError> {
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        Ok(WebhookSink {
+            client,
+            url: url.to_string(),
+            batch_size: 100,
+            flush_interval: Duration::from_secs(5),
+            queue_size: 10,
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+        })
+    }
+
+    /// Findings per request
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Longest a finding waits for its batch to fill up
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Batches waiting for delivery before new ones are dropped
+    pub fn queue_size(mut self, size: usize) -> Self {
+        self.queue_size = size.max(1);
+        self
+    }
+
+    /// Attempts after the first failed one, for retryable failures
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Wait before the first retry, doubled on each following one
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Deliver the findings of a crawl until its stream ends
+    pub async fn run(self, mut events: EventStream) -> WebhookStats {
+        let (batch_size, flush_interval) = (self.batch_size, self.flush_interval);
+        let (queue, batches) = mpsc::channel(self.queue_size);
+        let delivery = tokio::spawn(self.deliver(batches));
+
+        let mut dropped = 0;
+        let mut batch = Vec::new();
+        let mut deadline = None;
+        loop {
+            let event = match deadline {
+                Some(at) => match tokio::time::timeout_at(at, events.recv()).await {
+                    Ok(event) => event,
+                    // The oldest finding waited long enough
+                    Err(_) => {
+                        dropped += enqueue(&queue, &mut batch);
+                        deadline = None;
+                        continue;
+                    }
+                },
+                None => events.recv().await,
+            };
+            let Some(event) = event else {
+                break;
+            };
+            if !event.is_finding() {
+                continue;
+            }
+            if batch.is_empty() {
+                deadline = Some(Instant::now() + flush_interval);
+            }
+            batch.push(event);
+            if batch.len() >= batch_size {
+                dropped += enqueue(&queue, &mut batch);
+                deadline = None;
+            }
+        }
+        dropped += enqueue(&queue, &mut batch);
+        drop(queue);
+
+        let mut stats = delivery.await.unwrap_or_default();
+        stats.dropped += dropped;
+        stats
+    }
+
+    /// POST queued batches one at a time until the queue is closed
+    async fn deliver(self, mut batches: mpsc::Receiver<Vec<CrawlEvent>>) -> WebhookStats {
+        let mut stats = WebhookStats::default();
+        while let Some(batch) = batches.recv().await {
+            let count = batch.len() as u64;
+            match self.post(&batch).await {
+                Ok(()) => {
+                    debug!(findings = count, "Webhook batch delivered");
+                    stats.sent += count;
+                    stats.batches += 1;
+                }
+                Err(e) => {
+                    error!(kind = e.kind(), error = %e, findings = count, "Webhook batch dropped");
+                    stats.dropped += count;
+                }
+            }
+        }
+        stats
+    }
+
+    /// POST a batch, retrying failures that may pass later with exponential backoff
+    async fn post(&self, batch: &[CrawlEvent]) -> Result<(), CrawlError> {
+        let body = serde_json::to_vec(batch).map_err(|e| CrawlError::Request(e.to_string()))?;
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(CrawlError::from)
+                .and_then(|response| match response.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(CrawlError::HttpStatus(status.as_u16())),
+                });
+            match result {
+                Err(e) if e.is_retryable() && attempt < self.max_retries => {
+                    attempt += 1;
+                    warn!(kind = e.kind(), error = %e, attempt, "Webhook request failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Hand a batch over for delivery, returns how many findings were dropped because the queue is full
+fn enqueue(queue: &mpsc::Sender<Vec<CrawlEvent>>, batch: &mut Vec<CrawlEvent>) -> u64 {
+    if batch.is_empty() {
+        return 0;
+    }
+    match queue.try_send(std::mem::take(batch)) {
+        Ok(()) => 0,
+        Err(TrySendError::Full(batch) | TrySendError::Closed(batch)) => {
+            warn!(findings = batch.len(), "Webhook is falling behind, dropping findings");
+            batch.len() as u64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Events;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn findings() -> Vec<CrawlEvent> {
+        let url = "https://a.fr/".to_string();
+        vec![
+            CrawlEvent::PageFetched { url: url.clone(), status: 200, bytes: 10, depth: 1 },
+            CrawlEvent::EmailFound { email: "x@a.fr".to_string(), url: url.clone() },
+            CrawlEvent::PhoneFound { phone: "0102030405".to_string(), url: url.clone() },
+            CrawlEvent::EmailFound { email: "y@a.fr".to_string(), url },
+            CrawlEvent::CrawlFinished { run: 1, stop_reason: "queue_exhausted".to_string(), pages: 1 },
+        ]
+    }
+
+    /// Emit `events` to a new subscription in the background, then end it
+    fn stream(events: Vec<CrawlEvent>) -> EventStream {
+        let emitter = Arc::new(Events::default());
+        let stream = emitter.subscribe(2);
+        tokio::spawn(async move {
+            for event in events {
+                emitter.emit(event).await;
+            }
+            emitter.close();
+        });
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_write_ndjson() {
+        let mut out = Vec::new();
+        assert_eq!(write_ndjson(stream(findings()), &mut out).await.unwrap(), 3);
+        let lines: Vec<serde_json::Value> =
+            String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "email_found");
+        assert_eq!(lines[1]["phone"], "0102030405");
+    }
+
+    /// Answer 503 to the first `failures` requests and 200 after, keeping the bodies
+    async fn serve_webhook(failures: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        tokio::spawn(async move {
+            let mut requests = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                // Read the headers, then the body up to its length
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap_or(0);
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                        let length = headers
+                            .lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                            .and_then(|length| length.trim().parse().ok())
+                            .unwrap_or(0);
+                        if body.len() >= length || n == 0 {
+                            received.lock().unwrap().push(body.to_string());
+                            break;
+                        }
+                    }
+                    if n == 0 {
+                        break;
+                    }
+                }
+                requests += 1;
+                let status = if requests <= failures { "503 Service Unavailable" } else { "200 OK" };
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{}/hook", addr), bodies)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_batches_and_retries() {
+        let (url, bodies) = serve_webhook(1).await;
+        let sink = WebhookSink::new(&url).unwrap().batch_size(2).retry_delay(Duration::from_millis(10));
+        let stats = sink.run(stream(findings())).await;
+        assert_eq!(stats, WebhookStats { sent: 3, batches: 2, dropped: 0 });
+
+        // The first batch failed once and was sent again
+        let bodies = bodies.lock().unwrap().clone();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[0], bodies[1]);
+        let batch: Vec<serde_json::Value> = serde_json::from_str(&bodies[1]).unwrap();
+        assert_eq!((batch[0]["email"].as_str(), batch[1]["phone"].as_str()), (Some("x@a.fr"), Some("0102030405")));
+
+        // Retries are given up after max_retries
+        let (url, bodies) = serve_webhook(usize::MAX).await;
+        let sink = WebhookSink::new(&url).unwrap().max_retries(2).retry_delay(Duration::from_millis(1));
+        let stats = sink.run(stream(findings())).await;
+        assert_eq!(stats, WebhookStats { sent: 0, batches: 0, dropped: 3 });
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_does_not_hold_the_crawl_back() {
+        // Each batch takes a second to fail, the crawl must not wait for it
+        let (url, _) = serve_webhook(usize::MAX).await;
+        let sink = WebhookSink::new(&url)
+            .unwrap()
+            .batch_size(1)
+            .queue_size(1)
+            .max_retries(1)
+            .retry_delay(Duration::from_secs(1));
+        let emitter = Arc::new(Events::default());
+        let stream = emitter.subscribe(1);
+        let delivery = tokio::spawn(sink.run(stream));
+
+        let emitted = async {
+            for n in 0..10 {
+                let email = CrawlEvent::EmailFound { email: format!("{}@a.fr", n), url: "https://a.fr/".to_string() };
+                emitter.emit(email).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_millis(500), emitted).await.expect("the webhook held the crawl back");
+        emitter.close();
+
+        // One batch in flight, one queued, the rest dropped right away; all fail in the end
+        let stats = delivery.await.unwrap();
+        assert_eq!(stats, WebhookStats { sent: 0, batches: 0, dropped: 10 });
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Live counters of a crawl session, updated by the workers
+pub struct CrawlStats {
+    pages: AtomicU64,
+    in_flight: AtomicU64,
+    emails: AtomicU64,
+    phones: AtomicU64,
+    images: AtomicU64,
+    // Error counts by kind
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    // Fetched pages by HTTP status
+    statuses: Mutex<BTreeMap<u16, u64>>,
+    // URL each worker is processing
+    current: Vec<Mutex<Option<String>>>,
+}
+
+/// Counters read at one point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Pages fetched in this session
+    pub pages: u64,
+    /// Fetches waiting for a response
+    pub in_flight: u64,
+    /// New emails, phones and face images stored
+    pub emails: u64,
+    pub phones: u64,
+    pub images: u64,
+    /// Failed fetches and storage errors
+    pub errors: u64,
+}
+
+impl CrawlStats {
+    pub fn new(workers: usize) -> Self {
+        CrawlStats {
+            pages: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            emails: AtomicU64::new(0),
+            phones: AtomicU64::new(0),
+            images: AtomicU64::new(0),
+            errors: Mutex::new(BTreeMap::new()),
+            statuses: Mutex::new(BTreeMap::new()),
+            current: (0..workers).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    pub fn add_page(&self) {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn fetch_started(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn fetch_finished(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn add_emails(&self, count: u64) {
+        self.emails.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_phones(&self, count: u64) {
+        self.phones.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_image(&self) {
+        self.images.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an error, `kind` groups them in the summary
+    pub fn add_error(&self, kind: &'static str) {
+        *self.errors.lock().unwrap().entry(kind).or_insert(0) += 1;
+    }
+
+    /// Count a fetched page's HTTP status
+    pub fn add_status(&self, status: u16) {
+        *self.statuses.lock().unwrap().entry(status).or_insert(0) += 1;
+    }
+
+    pub fn errors_by_kind(&self) -> BTreeMap<String, u64> {
+        self.errors.lock().unwrap().iter().map(|(kind, count)| (kind.to_string(), *count)).collect()
+    }
+
+    pub fn status_counts(&self) -> BTreeMap<u16, u64> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    /// Set or clear the URL a worker is processing
+    pub fn set_current(&self, worker: usize, url: Option<&str>) {
+        if let Some(current) = self.current.get(worker) {
+            *current.lock().unwrap() = url.map(str::to_string);
+        }
+    }
+
+    /// URL a worker is processing, `None` when idle
+    pub fn current(&self, worker: usize) -> Option<String> {
+        self.current.get(worker).and_then(|current| current.lock().unwrap().clone())
+    }
+
+    pub fn workers(&self) -> usize {
+        self.current.len()
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            pages: self.pages.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            emails: self.emails.load(Ordering::Relaxed),
+            phones: self.phones.load(Ordering::Relaxed),
+            images: self.images.load(Ordering::Relaxed),
+            errors: self.errors.lock().unwrap().values().sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_workers() {
+        let stats = CrawlStats::new(2);
+        stats.add_page();
+        stats.fetch_started();
+        stats.fetch_started();
+        stats.fetch_finished();
+        stats.add_emails(3);
+        stats.add_error("timeout");
+        stats.add_error("timeout");
+        stats.add_error("db");
+        stats.add_status(200);
+
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.pages, snapshot.in_flight, snapshot.emails, snapshot.errors), (1, 1, 3, 3));
+        assert_eq!(stats.errors_by_kind(), BTreeMap::from([("db".to_string(), 1), ("timeout".to_string(), 2)]));
+        assert_eq!(stats.status_counts(), BTreeMap::from([(200, 1)]));
+
+        stats.set_current(1, Some("https://a.fr/"));
+        assert_eq!(stats.current(1).as_deref(), Some("https://a.fr/"));
+        assert_eq!(stats.current(0), None);
+        stats.set_current(1, None);
+        assert_eq!(stats.current(1), None);
+        // Out of range workers are ignored
+        stats.set_current(5, Some("https://a.fr/"));
+        assert_eq!(stats.current(5), None);
+    }
+}
@@ -0,0 +1,239 @@
+use super::memory::SightingKind;
+use super::{MemoryStorage, Result, RunRecord, Storage};
+use crate::error::CrawlError;
+use crate::strategy::CrawlStrategy;
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+/// Findings appended to a JSON Lines file, with the queue kept in memory
+///
+/// Each new sighting becomes one line, written as soon as it is found so an
+/// interrupted crawl keeps what it found. Runs can't be resumed.
+pub struct JsonlStorage {
+    inner: MemoryStorage,
+    file: Arc<Mutex<BufWriter<File>>>,
+}
+
+/// One line of the findings file
+#[derive(Serialize)]
+struct Finding<'a> {
+    kind: &'a str,
+    value: &'a str,
+    source_url: &'a str,
+    run_id: i64,
+    found_at: String,
+}
+
+impl JsonlStorage {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonlStorage {
+            inner: MemoryStorage::new(),
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Append findings of one kind and flush them
+    fn append(&self, kind: &str, values: &[String], source_url: &str) -> Result<()> {
+        let found_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let mut file = self.file.lock().unwrap();
+        for value in values {
+            let finding = Finding {
+                kind,
+                value,
+                source_url,
+                run_id: self.inner.run_id(),
+                found_at: found_at.clone(),
+            };
+            serde_json::to_writer(&mut *file, &finding)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Storage for JsonlStorage {
+    fn run_id(&self) -> i64 {
+        self.inner.run_id()
+    }
+
+    fn begin_run(&self, seeds: &str, args: &str) -> Result<i64> {
+        self.inner.begin_run(seeds, args)
+    }
+
+    fn find_run(&self, id: Option<i64>) -> Result<Option<RunRecord>> {
+        self.inner.find_run(id)
+    }
+
+    fn for_run(&self, id: i64) -> Arc<dyn Storage> {
+        Arc::new(JsonlStorage { inner: self.inner.for_run(id), file: self.file.clone() })
+    }
+
+    fn save_progress(&self, elapsed_ms: u64, pages: u64) -> Result<()> {
+        self.inner.save_progress(elapsed_ms, pages)
+    }
+
+    fn finish_run(&self, stop_reason: &str, elapsed_ms: u64, pages: u64) -> Result<()> {
+        self.inner.finish_run(stop_reason, elapsed_ms, pages)
+    }
+
+    fn queue_urls(&self, urls: Vec<(String, u32, i64)>) -> Result<usize> {
+        self.inner.queue_urls(urls)
+    }
+
+    fn pop_url(&self, strategy: CrawlStrategy) -> Result<Option<(String, u32)>> {
+        self.inner.pop_url(strategy)
+    }
+
+    fn complete_url(&self, url: &str) -> Result<()> {
+        self.inner.complete_url(url)
+    }
+
+    fn defer_url(&self, url: &str) -> Result<()> {
+        self.inner.defer_url(url)
+    }
+
+    fn reset_processing(&self) -> Result<u64> {
+        self.inner.reset_processing()
+    }
+
+    fn pending_count(&self) -> Result<u64> {
+        self.inner.pending_count()
+    }
+
+    fn is_visited(&self, url: &str) -> Result<bool> {
+        self.inner.is_visited(url)
+    }
+
+    fn filter_new_urls(&self, urls: &[String]) -> Result<HashSet<String>> {
+        self.inner.filter_new_urls(urls)
+    }
+
+    fn mark_visited(&self, url: &str) -> Result<()> {
+        self.inner.mark_visited(url)
+    }
+
+    fn for_each_seen_url(&self, f: &mut dyn FnMut(&str, bool)) -> Result<()> {
+        self.inner.for_each_seen_url(f)
+    }
+
+    fn add_host_usage(&self, host: &str, bytes: u64) -> Result<()> {
+        self.inner.add_host_usage(host, bytes)
+    }
+
+    fn get_host_usage(&self) -> Result<Vec<(String, u64, u64)>> {
+        self.inner.get_host_usage()
+    }
+
+    fn insert_page(
+        &self,
+        url: &str,
+        status: Option<u16>,
+        fingerprint: Option<(u64, u64)>,
+        duplicate_of: Option<&str>,
+    ) -> Result<()> {
+        self.inner.insert_page(url, status, fingerprint, duplicate_of)
+    }
+
+    fn get_page_fingerprints(&self) -> Result<Vec<(String, u64, u64)>> {
+        self.inner.get_page_fingerprints()
+    }
+
+    fn store_page_content(&self, _url: &str, _html: &str) -> Result<()> {
+        // Page content only feeds `reextract`, which reads the SQLite page store
+        Ok(())
+    }
+
+    fn insert_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize> {
+        let new_emails = self.inner.record_sightings(SightingKind::Email, emails, source_url);
+        self.append("email", &new_emails, source_url)?;
+        Ok(new_emails.len())
+    }
+
+    fn insert_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize> {
+        let new_phones = self.inner.record_sightings(SightingKind::Phone, phones, source_url);
+        self.append("phone", &new_phones, source_url)?;
+        Ok(new_phones.len())
+    }
+
+    fn insert_image(&self, uuid: &str, page_url: &str, image_url: &str) -> Result<bool> {
+        let inserted = self.inner.insert_image(uuid, page_url, image_url)?;
+        if inserted {
+            self.append("image", &[uuid.to_string()], page_url)?;
+        }
+        Ok(inserted)
+    }
+
+    fn record_traps(&self, traps: Vec<(String, String, String)>) -> Result<Vec<(String, String, String)>> {
+        self.inner.record_traps(traps)
+    }
+
+    fn record_error(&self, url: &str, error: &CrawlError) -> Result<()> {
+        self.inner.record_error(url, error)
+    }
+
+    fn requeue_failed(&self) -> Result<u64> {
+        self.inner.requeue_failed()
+    }
+
+    fn get_error_count(&self) -> Result<u64> {
+        self.inner.get_error_count()
+    }
+
+    fn get_email_count(&self) -> Result<u64> {
+        self.inner.get_email_count()
+    }
+
+    fn get_total_entries(&self) -> Result<u64> {
+        self.inner.get_total_entries()
+    }
+
+    fn get_phone_count(&self) -> Result<u64> {
+        self.inner.get_phone_count()
+    }
+
+    fn get_image_count(&self) -> Result<u64> {
+        self.inner.get_image_count()
+    }
+
+    fn get_trap_count(&self) -> Result<u64> {
+        self.inner.get_trap_count()
+    }
+
+    fn get_duplicate_count(&self) -> Result<u64> {
+        self.inner.get_duplicate_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDb;
+
+    #[test]
+    fn test_appends_new_sightings() {
+        let temp = TempDb::with_extension("jsonl");
+        let storage = JsonlStorage::open(temp.path()).unwrap();
+        let storage = storage.for_run(storage.begin_run("[]", "{}").unwrap());
+
+        let emails = vec!["x@a.fr".to_string(), "y@a.fr".to_string()];
+        assert_eq!(storage.insert_emails(emails.clone(), "https://a.fr/").unwrap(), 2);
+        assert_eq!(storage.insert_emails(emails, "https://a.fr/").unwrap(), 0);
+        assert_eq!(storage.insert_phones(vec!["+33123456789".to_string()], "https://a.fr/").unwrap(), 1);
+
+        let text = std::fs::read_to_string(temp.path()).unwrap();
+        let lines: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["kind"], "email");
+        assert_eq!(lines[0]["value"], "x@a.fr");
+        assert_eq!(lines[0]["source_url"], "https://a.fr/");
+        assert_eq!(lines[0]["run_id"], 1);
+        assert_eq!(lines[2]["kind"], "phone");
+    }
+}
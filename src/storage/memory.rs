@@ -0,0 +1,417 @@
+use super::{Result, RunRecord, Storage};
+use crate::error::CrawlError;
+use crate::strategy::CrawlStrategy;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Storage kept in memory for tests and short ad-hoc crawls
+///
+/// Everything is lost on exit, so runs can't be resumed, and only the latest
+/// run is kept. Picking the next URL scans the queue, which is fine for the
+/// small crawls this is meant for.
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<MemoryState>>,
+    run_id: i64,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // Id of the latest run
+    last_run: i64,
+    queue: Vec<QueuedUrl>,
+    queued: HashMap<String, usize>,
+    visited: HashSet<String>,
+    host_usage: HashMap<String, (u64, u64)>,
+    pages: HashMap<String, StoredPage>,
+    page_contents: HashMap<String, String>,
+    // (value, source url) sightings
+    emails: HashSet<(String, String)>,
+    phones: HashSet<(String, String)>,
+    images: HashSet<String>,
+    traps: HashSet<(String, String)>,
+    errors: Vec<FailedUrl>,
+}
+
+struct FailedUrl {
+    url: String,
+    retryable: bool,
+    retried: bool,
+}
+
+struct StoredPage {
+    fingerprint: Option<(u64, u64)>,
+    duplicate_of: Option<String>,
+}
+
+struct QueuedUrl {
+    url: String,
+    depth: u32,
+    priority: i64,
+    status: UrlStatus,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UrlStatus {
+    Pending,
+    Processing,
+    Done,
+    Deferred,
+}
+
+/// Kind of finding kept as (value, source url) sightings
+#[derive(Clone, Copy)]
+pub(super) enum SightingKind {
+    Email,
+    Phone,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// A handle on run `id`, sharing this storage's state
+    pub fn for_run(&self, id: i64) -> MemoryStorage {
+        MemoryStorage { state: self.state.clone(), run_id: id }
+    }
+
+    /// Record values seen on a page, returns those not yet seen on it
+    pub(super) fn record_sightings(&self, kind: SightingKind, values: Vec<String>, source_url: &str) -> Vec<String> {
+        let mut state = self.state.lock().unwrap();
+        let sightings = match kind {
+            SightingKind::Email => &mut state.emails,
+            SightingKind::Phone => &mut state.phones,
+        };
+        values
+            .into_iter()
+            .filter(|value| sightings.insert((value.clone(), source_url.to_string())))
+            .collect()
+    }
+
+    fn set_url_status(&self, url: &str, status: UrlStatus) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&index) = state.queued.get(url) {
+            state.queue[index].status = status;
+        }
+        Ok(())
+    }
+}
+
+/// Whether queue entry `a` (at index `i`) comes before `b` (at `j`) in the strategy's order
+fn comes_before(strategy: CrawlStrategy, a: &QueuedUrl, i: usize, b: &QueuedUrl, j: usize) -> bool {
+    match strategy {
+        CrawlStrategy::Bfs => (a.depth, i) < (b.depth, j),
+        CrawlStrategy::Dfs => (a.depth, i) > (b.depth, j),
+        CrawlStrategy::BestFirst => (-a.priority, i) < (-b.priority, j),
+    }
+}
+
+/// Count distinct values in a set of (value, source url) sightings
+fn distinct_values(sightings: &HashSet<(String, String)>) -> u64 {
+    sightings.iter().map(|(value, _)| value).collect::<HashSet<_>>().len() as u64
+}
+
+impl Storage for MemoryStorage {
+    fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    fn begin_run(&self, _seeds: &str, _args: &str) -> Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        let last_run = state.last_run + 1;
+        *state = MemoryState { last_run, ..Default::default() };
+        Ok(last_run)
+    }
+
+    fn find_run(&self, _id: Option<i64>) -> Result<Option<RunRecord>> {
+        Ok(None)
+    }
+
+    fn for_run(&self, id: i64) -> Arc<dyn Storage> {
+        Arc::new(MemoryStorage::for_run(self, id))
+    }
+
+    fn save_progress(&self, _elapsed_ms: u64, _pages: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish_run(&self, _stop_reason: &str, _elapsed_ms: u64, _pages: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn queue_urls(&self, urls: Vec<(String, u32, i64)>) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let mut inserted = 0;
+        for (url, depth, priority) in urls {
+            if state.queued.contains_key(&url) {
+                continue;
+            }
+            let index = state.queue.len();
+            state.queued.insert(url.clone(), index);
+            state.queue.push(QueuedUrl { url, depth, priority, status: UrlStatus::Pending });
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+
+    fn pop_url(&self, strategy: CrawlStrategy) -> Result<Option<(String, u32)>> {
+        let mut state = self.state.lock().unwrap();
+        let mut next: Option<usize> = None;
+        for (i, entry) in state.queue.iter().enumerate() {
+            if entry.status != UrlStatus::Pending {
+                continue;
+            }
+            if next.is_none_or(|j| comes_before(strategy, entry, i, &state.queue[j], j)) {
+                next = Some(i);
+            }
+        }
+
+        Ok(next.map(|i| {
+            let entry = &mut state.queue[i];
+            entry.status = UrlStatus::Processing;
+            (entry.url.clone(), entry.depth)
+        }))
+    }
+
+    fn complete_url(&self, url: &str) -> Result<()> {
+        self.set_url_status(url, UrlStatus::Done)
+    }
+
+    fn defer_url(&self, url: &str) -> Result<()> {
+        self.set_url_status(url, UrlStatus::Deferred)
+    }
+
+    fn reset_processing(&self) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut count = 0;
+        for entry in &mut state.queue {
+            if matches!(entry.status, UrlStatus::Processing | UrlStatus::Deferred) {
+                entry.status = UrlStatus::Pending;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn pending_count(&self) -> Result<u64> {
+        let state = self.state.lock().unwrap();
+        Ok(state.queue.iter().filter(|entry| entry.status == UrlStatus::Pending).count() as u64)
+    }
+
+    fn is_visited(&self, url: &str) -> Result<bool> {
+        Ok(self.state.lock().unwrap().visited.contains(url))
+    }
+
+    fn filter_new_urls(&self, urls: &[String]) -> Result<HashSet<String>> {
+        let state = self.state.lock().unwrap();
+        Ok(urls
+            .iter()
+            .filter(|url| !state.visited.contains(*url) && !state.queued.contains_key(*url))
+            .cloned()
+            .collect())
+    }
+
+    fn mark_visited(&self, url: &str) -> Result<()> {
+        self.state.lock().unwrap().visited.insert(url.to_string());
+        Ok(())
+    }
+
+    fn for_each_seen_url(&self, f: &mut dyn FnMut(&str, bool)) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        for url in &state.visited {
+            f(url, true);
+        }
+        for entry in state.queue.iter().filter(|entry| !state.visited.contains(&entry.url)) {
+            f(&entry.url, false);
+        }
+        Ok(())
+    }
+
+    fn add_host_usage(&self, host: &str, bytes: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let usage = state.host_usage.entry(host.to_string()).or_default();
+        usage.0 += 1;
+        usage.1 += bytes;
+        Ok(())
+    }
+
+    fn get_host_usage(&self) -> Result<Vec<(String, u64, u64)>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.host_usage.iter().map(|(host, (pages, bytes))| (host.clone(), *pages, *bytes)).collect())
+    }
+
+    fn insert_page(
+        &self,
+        url: &str,
+        _status: Option<u16>,
+        fingerprint: Option<(u64, u64)>,
+        duplicate_of: Option<&str>,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let page = StoredPage { fingerprint, duplicate_of: duplicate_of.map(str::to_string) };
+        state.pages.insert(url.to_string(), page);
+        Ok(())
+    }
+
+    fn get_page_fingerprints(&self) -> Result<Vec<(String, u64, u64)>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .pages
+            .iter()
+            .filter(|(_, page)| page.duplicate_of.is_none())
+            .filter_map(|(url, page)| page.fingerprint.map(|(hash, simhash)| (url.clone(), hash, simhash)))
+            .collect())
+    }
+
+    fn store_page_content(&self, url: &str, html: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.page_contents.insert(url.to_string(), html.to_string());
+        Ok(())
+    }
+
+    fn insert_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize> {
+        Ok(self.record_sightings(SightingKind::Email, emails, source_url).len())
+    }
+
+    fn insert_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize> {
+        Ok(self.record_sightings(SightingKind::Phone, phones, source_url).len())
+    }
+
+    fn insert_image(&self, uuid: &str, _page_url: &str, _image_url: &str) -> Result<bool> {
+        Ok(self.state.lock().unwrap().images.insert(uuid.to_string()))
+    }
+
+    fn record_traps(&self, traps: Vec<(String, String, String)>) -> Result<Vec<(String, String, String)>> {
+        let mut state = self.state.lock().unwrap();
+        Ok(traps
+            .into_iter()
+            .filter(|(pattern, reason, _)| state.traps.insert((pattern.clone(), reason.clone())))
+            .collect())
+    }
+
+    fn record_error(&self, url: &str, error: &CrawlError) -> Result<()> {
+        let failed = FailedUrl { url: url.to_string(), retryable: error.is_retryable(), retried: false };
+        self.state.lock().unwrap().errors.push(failed);
+        Ok(())
+    }
+
+    fn requeue_failed(&self) -> Result<u64> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let mut count = 0;
+        for failed in state.errors.iter_mut().filter(|failed| failed.retryable && !failed.retried) {
+            failed.retried = true;
+            state.visited.remove(&failed.url);
+            if let Some(&index) = state.queued.get(&failed.url) {
+                if state.queue[index].status != UrlStatus::Pending {
+                    state.queue[index].status = UrlStatus::Pending;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn get_error_count(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().errors.len() as u64)
+    }
+
+    fn get_email_count(&self) -> Result<u64> {
+        Ok(distinct_values(&self.state.lock().unwrap().emails))
+    }
+
+    fn get_total_entries(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().emails.len() as u64)
+    }
+
+    fn get_phone_count(&self) -> Result<u64> {
+        Ok(distinct_values(&self.state.lock().unwrap().phones))
+    }
+
+    fn get_image_count(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().images.len() as u64)
+    }
+
+    fn get_trap_count(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().traps.len() as u64)
+    }
+
+    fn get_duplicate_count(&self) -> Result<u64> {
+        let state = self.state.lock().unwrap();
+        Ok(state.pages.values().filter(|page| page.duplicate_of.is_some()).count() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_order_by_strategy() {
+        let storage = MemoryStorage::new();
+        storage.begin_run("[]", "{}").unwrap();
+        storage
+            .queue_urls(vec![
+                ("https://a.fr/".to_string(), 1, 0),
+                ("https://a.fr/deep".to_string(), 3, -20),
+                ("https://a.fr/contact".to_string(), 2, 90),
+            ])
+            .unwrap();
+        assert!(!storage.queue_url("https://a.fr/", 1, 0).unwrap());
+
+        let pop = |strategy| storage.pop_url(strategy).unwrap().unwrap().0;
+        assert_eq!(pop(CrawlStrategy::BestFirst), "https://a.fr/contact");
+        assert_eq!(pop(CrawlStrategy::Dfs), "https://a.fr/deep");
+        assert_eq!(pop(CrawlStrategy::Bfs), "https://a.fr/");
+        assert!(storage.pop_url(CrawlStrategy::Bfs).unwrap().is_none());
+        assert_eq!(storage.reset_processing().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_visited_and_findings() {
+        let storage = MemoryStorage::new();
+        storage.begin_run("[]", "{}").unwrap();
+        storage.queue_url("https://a.fr/", 1, 0).unwrap();
+        storage.mark_visited("https://a.fr/seen").unwrap();
+
+        let urls = vec!["https://a.fr/".to_string(), "https://a.fr/seen".to_string(), "https://a.fr/new".to_string()];
+        let new_urls = storage.filter_new_urls(&urls).unwrap();
+        assert_eq!(new_urls, HashSet::from(["https://a.fr/new".to_string()]));
+
+        let mut seen = HashSet::new();
+        storage.for_each_seen_url(&mut |url, visited| {
+            seen.insert((url.to_string(), visited));
+        })
+        .unwrap();
+        assert_eq!(seen, HashSet::from([("https://a.fr/".to_string(), false), ("https://a.fr/seen".to_string(), true)]));
+
+        let emails = vec!["x@a.fr".to_string()];
+        assert_eq!(storage.insert_emails(emails.clone(), "https://a.fr/").unwrap(), 1);
+        assert_eq!(storage.insert_emails(emails.clone(), "https://a.fr/").unwrap(), 0);
+        assert_eq!(storage.insert_emails(emails, "https://a.fr/team").unwrap(), 1);
+        assert_eq!(storage.get_stats().unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn test_requeue_failed() {
+        let storage = MemoryStorage::new();
+        storage.begin_run("[]", "{}").unwrap();
+        storage.queue_url("https://a.fr/", 1, 0).unwrap();
+        storage.queue_url("https://a.fr/big", 2, 0).unwrap();
+        while let Some((url, _)) = storage.pop_url(CrawlStrategy::Bfs).unwrap() {
+            storage.mark_visited(&url).unwrap();
+            storage.complete_url(&url).unwrap();
+        }
+        storage.record_error("https://a.fr/", &CrawlError::Timeout).unwrap();
+        storage.record_error("https://a.fr/big", &CrawlError::BodyTooLarge { limit: 10 }).unwrap();
+        assert_eq!(storage.get_error_count().unwrap(), 2);
+
+        // Only the timeout is worth another try, and only once
+        assert_eq!(storage.requeue_failed().unwrap(), 1);
+        assert!(!storage.is_visited("https://a.fr/").unwrap());
+        assert!(storage.is_visited("https://a.fr/big").unwrap());
+        assert_eq!(storage.pending_count().unwrap(), 1);
+        assert_eq!(storage.requeue_failed().unwrap(), 0);
+    }
+}
@@ -0,0 +1,217 @@
+mod jsonl;
+mod memory;
+mod sqlite;
+
+pub use jsonl::JsonlStorage;
+pub use memory::MemoryStorage;
+
+use crate::error::CrawlError;
+use crate::strategy::CrawlStrategy;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// Error from a storage backend
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(e) => write!(f, "{}", e),
+            StorageError::Io(e) => write!(f, "{}", e),
+            StorageError::Json(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Json(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+/// Where a crawl keeps its queue and findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageKind {
+    /// SQLite database (`--db`), resumable
+    Sqlite,
+    /// In memory, lost on exit (tests and short ad-hoc crawls)
+    Memory,
+    /// Queue in memory, findings appended to a JSONL file (`--jsonl`)
+    Jsonl,
+}
+
+/// A crawl run as stored by a backend
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub id: i64,
+    pub seeds: String,
+    /// Crawl options the run was started with (JSON)
+    pub args: String,
+    pub stop_reason: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Queue, visited set and findings of a crawl
+///
+/// Every method blocks, async code should go through [`call`]. Queue, visited,
+/// budget and findings operations apply to the run of the handle, see
+/// [`Storage::for_run`].
+pub trait Storage: Send + Sync {
+    /// Id of the run this handle works on, 0 for none
+    fn run_id(&self) -> i64;
+
+    /// Record a new run with its seeds and arguments (JSON), returns its id
+    fn begin_run(&self, seeds: &str, args: &str) -> Result<i64>;
+
+    /// A recorded run, the latest one if no id is given
+    fn find_run(&self, id: Option<i64>) -> Result<Option<RunRecord>>;
+
+    /// A handle on run `id`, sharing this storage
+    fn for_run(&self, id: i64) -> Arc<dyn Storage>;
+
+    /// Record the time spent and pages crawled so far, kept if the crawl is interrupted
+    fn save_progress(&self, elapsed_ms: u64, pages: u64) -> Result<()>;
+
+    /// Record the end of the run
+    fn finish_run(&self, stop_reason: &str, elapsed_ms: u64, pages: u64) -> Result<()>;
+
+    /// Add URLs with their depth and priority, returns how many were new
+    fn queue_urls(&self, urls: Vec<(String, u32, i64)>) -> Result<usize>;
+
+    /// Add a URL to the queue (ignored if already queued)
+    fn queue_url(&self, url: &str, depth: u32, priority: i64) -> Result<bool> {
+        let inserted = self.queue_urls(vec![(url.to_string(), depth, priority)])?;
+        Ok(inserted > 0)
+    }
+
+    /// Claim the next pending URL in the strategy's order
+    fn pop_url(&self, strategy: CrawlStrategy) -> Result<Option<(String, u32)>>;
+
+    /// Mark a URL as completed
+    fn complete_url(&self, url: &str) -> Result<()>;
+
+    /// Keep a URL whose host ran out of budget for a later resume
+    fn defer_url(&self, url: &str) -> Result<()>;
+
+    /// Reset processing and deferred URLs to pending (for resume)
+    fn reset_processing(&self) -> Result<u64>;
+
+    /// Count of pending URLs
+    fn pending_count(&self) -> Result<u64>;
+
+    /// Whether a URL was already visited
+    fn is_visited(&self, url: &str) -> Result<bool>;
+
+    /// The URLs that are neither visited nor queued
+    fn filter_new_urls(&self, urls: &[String]) -> Result<HashSet<String>>;
+
+    /// Mark a URL as visited
+    fn mark_visited(&self, url: &str) -> Result<()>;
+
+    /// Call `f` with every queued or visited URL of the run and whether it was visited
+    fn for_each_seen_url(&self, f: &mut dyn FnMut(&str, bool)) -> Result<()>;
+
+    /// Add a crawled page and its size to the host's budget usage
+    fn add_host_usage(&self, host: &str, bytes: u64) -> Result<()>;
+
+    /// Budget usage per host as (host, pages, bytes)
+    fn get_host_usage(&self) -> Result<Vec<(String, u64, u64)>>;
+
+    /// Record a fetched page with its HTTP status, fingerprint and the page it duplicates
+    fn insert_page(
+        &self,
+        url: &str,
+        status: Option<u16>,
+        fingerprint: Option<(u64, u64)>,
+        duplicate_of: Option<&str>,
+    ) -> Result<()>;
+
+    /// Fingerprints of original (non-duplicate) pages
+    fn get_page_fingerprints(&self) -> Result<Vec<(String, u64, u64)>>;
+
+    /// Keep a page's HTML for re-extraction
+    fn store_page_content(&self, url: &str, html: &str) -> Result<()>;
+
+    /// Record emails seen on a page, returns how many were not yet seen on it
+    fn insert_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize>;
+
+    /// Record phone numbers seen on a page, returns how many were not yet seen on it
+    fn insert_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize>;
+
+    /// Record a saved image with the page that referenced it
+    fn insert_image(&self, uuid: &str, page_url: &str, image_url: &str) -> Result<bool>;
+
+    /// Record trapped URL patterns as (pattern, reason, example), returns the new ones
+    fn record_traps(&self, traps: Vec<(String, String, String)>) -> Result<Vec<(String, String, String)>>;
+
+    /// Record a URL that failed to crawl, or whose findings failed to process
+    fn record_error(&self, url: &str, error: &CrawlError) -> Result<()>;
+
+    /// Queue again the URLs with retryable errors, returns how many were requeued
+    fn requeue_failed(&self) -> Result<u64>;
+
+    /// Count of recorded errors
+    fn get_error_count(&self) -> Result<u64>;
+
+    /// Count of unique emails
+    fn get_email_count(&self) -> Result<u64>;
+
+    /// Count of email sightings
+    fn get_total_entries(&self) -> Result<u64>;
+
+    /// Count of unique emails and email sightings
+    fn get_stats(&self) -> Result<(u64, u64)> {
+        Ok((self.get_email_count()?, self.get_total_entries()?))
+    }
+
+    /// Count of unique phones
+    fn get_phone_count(&self) -> Result<u64>;
+
+    /// Count of saved images
+    fn get_image_count(&self) -> Result<u64>;
+
+    /// Count of trapped URL patterns
+    fn get_trap_count(&self) -> Result<u64>;
+
+    /// Count of pages flagged as duplicates
+    fn get_duplicate_count(&self) -> Result<u64>;
+}
+
+/// Run blocking storage work on the blocking thread pool
+///
+/// Keeps tokio workers free while a backend waits on I/O.
+pub async fn call<T, F>(storage: &Arc<dyn Storage>, f: F) -> T
+where
+    F: FnOnce(&dyn Storage) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let storage = storage.clone();
+    tokio::task::spawn_blocking(move || f(storage.as_ref()))
+        .await
+        .expect("storage task panicked")
+}
@@ -0,0 +1,151 @@
+use super::{Result, RunRecord, Storage};
+use crate::database::Database;
+use crate::error::CrawlError;
+use crate::strategy::CrawlStrategy;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The SQLite database as a crawl backend
+impl Storage for Database {
+    fn run_id(&self) -> i64 {
+        Database::run_id(self)
+    }
+
+    fn begin_run(&self, seeds: &str, args: &str) -> Result<i64> {
+        Ok(Database::begin_run(self, seeds, args)?)
+    }
+
+    fn find_run(&self, id: Option<i64>) -> Result<Option<RunRecord>> {
+        Ok(Database::find_run(self, id)?)
+    }
+
+    fn for_run(&self, id: i64) -> Arc<dyn Storage> {
+        Arc::new(Database::for_run(self, id))
+    }
+
+    fn save_progress(&self, elapsed_ms: u64, pages: u64) -> Result<()> {
+        Ok(Database::save_progress(self, elapsed_ms, pages)?)
+    }
+
+    fn finish_run(&self, stop_reason: &str, elapsed_ms: u64, pages: u64) -> Result<()> {
+        Ok(Database::finish_run(self, stop_reason, elapsed_ms, pages)?)
+    }
+
+    fn queue_urls(&self, urls: Vec<(String, u32, i64)>) -> Result<usize> {
+        Ok(Database::queue_urls(self, urls)?)
+    }
+
+    fn pop_url(&self, strategy: CrawlStrategy) -> Result<Option<(String, u32)>> {
+        Ok(Database::pop_url(self, strategy)?)
+    }
+
+    fn complete_url(&self, url: &str) -> Result<()> {
+        Ok(Database::complete_url(self, url)?)
+    }
+
+    fn defer_url(&self, url: &str) -> Result<()> {
+        Ok(Database::defer_url(self, url)?)
+    }
+
+    fn reset_processing(&self) -> Result<u64> {
+        Ok(Database::reset_processing(self)?)
+    }
+
+    fn pending_count(&self) -> Result<u64> {
+        Ok(Database::pending_count(self)?)
+    }
+
+    fn is_visited(&self, url: &str) -> Result<bool> {
+        Ok(Database::is_visited(self, url)?)
+    }
+
+    fn filter_new_urls(&self, urls: &[String]) -> Result<HashSet<String>> {
+        Ok(Database::filter_new_urls(self, urls)?)
+    }
+
+    fn mark_visited(&self, url: &str) -> Result<()> {
+        Ok(Database::mark_visited(self, url)?)
+    }
+
+    fn for_each_seen_url(&self, f: &mut dyn FnMut(&str, bool)) -> Result<()> {
+        Ok(Database::for_each_seen_url(self, f)?)
+    }
+
+    fn add_host_usage(&self, host: &str, bytes: u64) -> Result<()> {
+        Ok(Database::add_host_usage(self, host, bytes)?)
+    }
+
+    fn get_host_usage(&self) -> Result<Vec<(String, u64, u64)>> {
+        Ok(Database::get_host_usage(self)?)
+    }
+
+    fn insert_page(
+        &self,
+        url: &str,
+        status: Option<u16>,
+        fingerprint: Option<(u64, u64)>,
+        duplicate_of: Option<&str>,
+    ) -> Result<()> {
+        Ok(Database::insert_page(self, url, status, fingerprint, duplicate_of)?)
+    }
+
+    fn get_page_fingerprints(&self) -> Result<Vec<(String, u64, u64)>> {
+        Ok(Database::get_page_fingerprints(self)?)
+    }
+
+    fn store_page_content(&self, url: &str, html: &str) -> Result<()> {
+        Ok(Database::store_page_content(self, url, html)?)
+    }
+
+    fn insert_emails(&self, emails: Vec<String>, source_url: &str) -> Result<usize> {
+        Ok(Database::insert_emails(self, emails, source_url)?)
+    }
+
+    fn insert_phones(&self, phones: Vec<String>, source_url: &str) -> Result<usize> {
+        Ok(Database::insert_phones(self, phones, source_url)?)
+    }
+
+    fn insert_image(&self, uuid: &str, page_url: &str, image_url: &str) -> Result<bool> {
+        Ok(Database::insert_image(self, uuid, page_url, image_url)?)
+    }
+
+    fn record_traps(&self, traps: Vec<(String, String, String)>) -> Result<Vec<(String, String, String)>> {
+        Ok(Database::record_traps(self, traps)?)
+    }
+
+    fn record_error(&self, url: &str, error: &CrawlError) -> Result<()> {
+        Ok(Database::record_error(self, url, error)?)
+    }
+
+    fn requeue_failed(&self) -> Result<u64> {
+        Ok(Database::requeue_failed(self)?)
+    }
+
+    fn get_error_count(&self) -> Result<u64> {
+        Ok(Database::get_error_count(self)?)
+    }
+
+    fn get_email_count(&self) -> Result<u64> {
+        Ok(Database::get_email_count(self)?)
+    }
+
+    fn get_total_entries(&self) -> Result<u64> {
+        Ok(Database::get_total_entries(self)?)
+    }
+
+    fn get_phone_count(&self) -> Result<u64> {
+        Ok(Database::get_phone_count(self)?)
+    }
+
+    fn get_image_count(&self) -> Result<u64> {
+        Ok(Database::get_image_count(self)?)
+    }
+
+    fn get_trap_count(&self) -> Result<u64> {
+        Ok(Database::get_trap_count(self)?)
+    }
+
+    fn get_duplicate_count(&self) -> Result<u64> {
+        Ok(Database::get_duplicate_count(self)?)
+    }
+}
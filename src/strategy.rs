@@ -0,0 +1,81 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Score added when a keyword appears in the URL path
+const PATH_KEYWORD_SCORE: i64 = 100;
+/// Score added when a keyword appears in the link's anchor text
+const ANCHOR_KEYWORD_SCORE: i64 = 50;
+/// Score removed per level of depth
+const DEPTH_PENALTY: i64 = 10;
+
+/// Order in which queued URLs are crawled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrawlStrategy {
+    /// Breadth-first: shallowest URLs first
+    Bfs,
+    /// Depth-first: deepest, most recently queued URLs first
+    Dfs,
+    /// Best-first: highest priority score first
+    BestFirst,
+}
+
+impl CrawlStrategy {
+    /// SQL `ORDER BY` clause selecting the next URL from `url_queue`
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            CrawlStrategy::Bfs => "depth ASC, id ASC",
+            CrawlStrategy::Dfs => "depth DESC, id DESC",
+            CrawlStrategy::BestFirst => "priority DESC, id ASC",
+        }
+    }
+}
+
+/// Score a link for best-first crawling from its path, anchor text and depth
+pub fn score_url(url: &Url, anchor_text: &str, depth: u32, keywords: &[String]) -> i64 {
+    let path = url.path().to_lowercase();
+    let anchor = anchor_text.to_lowercase();
+    let mut score = 0;
+
+    for keyword in keywords {
+        let keyword = keyword.trim().to_lowercase();
+        if keyword.is_empty() {
+            continue;
+        }
+        if path.contains(&keyword) {
+            score += PATH_KEYWORD_SCORE;
+        }
+        if anchor.contains(&keyword) {
+            score += ANCHOR_KEYWORD_SCORE;
+        }
+    }
+
+    score - depth as i64 * DEPTH_PENALTY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_url() {
+        let keywords = vec!["contact".to_string(), "mentions-legales".to_string()];
+
+        let contact = Url::parse("https://example.com/contact").unwrap();
+        assert_eq!(score_url(&contact, "Contactez-nous", 1, &keywords), 140);
+
+        let legal = Url::parse("https://example.com/mentions-legales").unwrap();
+        assert_eq!(score_url(&legal, "Legal", 2, &keywords), 80);
+
+        let blog = Url::parse("https://example.com/blog/post-1").unwrap();
+        assert_eq!(score_url(&blog, "Read more", 3, &keywords), -30);
+    }
+
+    #[test]
+    fn test_deeper_links_score_lower() {
+        let url = Url::parse("https://example.com/about").unwrap();
+        let keywords = vec!["about".to_string()];
+        assert!(score_url(&url, "", 1, &keywords) > score_url(&url, "", 4, &keywords));
+    }
+}
@@ -0,0 +1,137 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Exit code when a command can't run: bad configuration, unusable database
+pub const EXIT_FATAL: i32 = 2;
+
+/// Hosts listed in the summary
+const TOP_HOSTS: usize = 10;
+
+/// How a finished crawl went, decides the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// No fetch or storage error (exit code 0)
+    Success,
+    /// The crawl finished but some fetches or writes failed (exit code 1)
+    PartialFailure,
+}
+
+impl Outcome {
+    pub fn from_errors(errors: u64) -> Self {
+        if errors == 0 {
+            Outcome::Success
+        } else {
+            Outcome::PartialFailure
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::PartialFailure => 1,
+        }
+    }
+}
+
+/// End-of-crawl statistics written by `--summary-json`
+#[derive(Debug, Serialize)]
+pub struct CrawlSummary {
+    pub run: i64,
+    pub start_url: String,
+    pub outcome: Outcome,
+    pub exit_code: i32,
+    pub stop_reason: String,
+    /// Start and end of this session, RFC 3339 UTC
+    pub started_at: String,
+    pub finished_at: String,
+    pub session_secs: f64,
+    /// Crawl time of the run, across resumes
+    pub crawl_secs: f64,
+    pub counters: Counters,
+    /// Pages fetched in this session by HTTP status
+    pub status_codes: BTreeMap<u16, u64>,
+    /// Errors of this session by kind
+    pub errors: BTreeMap<String, u64>,
+    pub top_hosts: Vec<HostSummary>,
+}
+
+/// Run totals, and what this session added
+#[derive(Debug, Default, Serialize)]
+pub struct Counters {
+    pub pages_crawled: u64,
+    pub session_pages: u64,
+    pub bytes_downloaded: u64,
+    pub new_emails: u64,
+    pub new_phones: u64,
+    pub new_images: u64,
+    pub unique_emails: u64,
+    pub unique_phones: u64,
+    pub total_email_entries: u64,
+    pub images_with_faces: u64,
+    pub trap_patterns: u64,
+    pub duplicate_pages: u64,
+    pub errors: u64,
+    /// Errors recorded over the run, across resumes
+    pub run_errors: u64,
+}
+
+/// Pages and bytes of a host over the run
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct HostSummary {
+    pub host: String,
+    pub pages: u64,
+    pub bytes: u64,
+}
+
+/// Busiest hosts by pages, from (host, pages, bytes) usage
+pub fn top_hosts(usage: Vec<(String, u64, u64)>) -> Vec<HostSummary> {
+    let mut hosts: Vec<HostSummary> =
+        usage.into_iter().map(|(host, pages, bytes)| HostSummary { host, pages, bytes }).collect();
+    hosts.sort_by(|a, b| b.pages.cmp(&a.pages).then_with(|| a.host.cmp(&b.host)));
+    hosts.truncate(TOP_HOSTS);
+    hosts
+}
+
+/// Current time for `started_at` and `finished_at`
+pub fn timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_and_top_hosts() {
+        assert_eq!(Outcome::from_errors(0).exit_code(), 0);
+        assert_eq!(Outcome::from_errors(3), Outcome::PartialFailure);
+        assert_eq!(Outcome::PartialFailure.exit_code(), 1);
+
+        let usage = (0..12).map(|i| (format!("h{:02}.fr", i), i % 4, 100)).collect();
+        let hosts = top_hosts(usage);
+        assert_eq!(hosts.len(), 10);
+        assert_eq!(hosts[0], HostSummary { host: "h03.fr".to_string(), pages: 3, bytes: 100 });
+        assert_eq!(hosts[1].host, "h07.fr");
+
+        let summary = CrawlSummary {
+            run: 1,
+            start_url: "https://a.fr/".to_string(),
+            outcome: Outcome::PartialFailure,
+            exit_code: 1,
+            stop_reason: "queue_exhausted".to_string(),
+            started_at: timestamp(),
+            finished_at: timestamp(),
+            session_secs: 1.5,
+            crawl_secs: 1.5,
+            counters: Counters::default(),
+            status_codes: BTreeMap::from([(200, 4), (404, 1)]),
+            errors: BTreeMap::from([("timeout".to_string(), 2)]),
+            top_hosts: hosts,
+        };
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["outcome"], "partial_failure");
+        assert_eq!(json["status_codes"]["404"], 1);
+        assert_eq!(json["errors"]["timeout"], 2);
+    }
+}
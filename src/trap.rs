@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use url::Url;
+
+/// Reason a URL was classified as a crawl trap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    UrlTooLong,
+    RepeatedSegments,
+    QueryVariants,
+    PatternLimit,
+}
+
+impl TrapKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrapKind::UrlTooLong => "url_too_long",
+            TrapKind::RepeatedSegments => "repeated_segments",
+            TrapKind::QueryVariants => "query_variants",
+            TrapKind::PatternLimit => "pattern_limit",
+        }
+    }
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A URL rejected by the trap detector
+#[derive(Debug, Clone)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub pattern: String,
+}
+
+/// Trap heuristic limits (0 disables a limit)
+#[derive(Debug, Clone, Copy)]
+pub struct TrapLimits {
+    pub max_url_length: usize,
+    pub max_repeated_segments: usize,
+    pub max_query_variants: usize,
+    pub max_pages_per_pattern: usize,
+}
+
+#[derive(Default)]
+struct TrapState {
+    // Distinct query strings seen per host + path
+    query_variants: HashMap<String, HashSet<String>>,
+    // Queued URLs per path pattern
+    pattern_counts: HashMap<String, usize>,
+}
+
+/// Detects infinite URL spaces (calendars, faceted search, repeating paths)
+pub struct TrapDetector {
+    limits: TrapLimits,
+    state: Mutex<TrapState>,
+}
+
+impl TrapDetector {
+    /// Create a new trap detector
+    pub fn new(limits: TrapLimits) -> Self {
+        TrapDetector {
+            limits,
+            state: Mutex::new(TrapState::default()),
+        }
+    }
+
+    /// Start checking a batch of URLs, waiting for the batch other workers hold
+    pub fn batch(&self) -> TrapBatch<'_> {
+        TrapBatch {
+            limits: &self.limits,
+            state: self.state.lock().unwrap(),
+            pending: TrapState::default(),
+        }
+    }
+}
+
+/// URLs checked together, only counted by the detector once recorded
+///
+/// URLs accepted by the batch count against the limits of its later checks,
+/// so one page can't exceed a limit on its own. A batch dropped without
+/// [`TrapBatch::record`] leaves the detector as it was. The batch holds the
+/// detector's lock until then, so concurrent batches can't exceed a limit together.
+pub struct TrapBatch<'a> {
+    limits: &'a TrapLimits,
+    state: MutexGuard<'a, TrapState>,
+    // Query variants and pattern counts added by the accepted URLs
+    pending: TrapState,
+}
+
+impl TrapBatch<'_> {
+    /// Check a URL against the trap heuristics, accepting it into the batch if it passes
+    pub fn check(&mut self, url: &Url) -> Option<Trap> {
+        let limits = self.limits;
+
+        if limits.max_url_length > 0 && url.as_str().len() > limits.max_url_length {
+            return Some(Trap {
+                kind: TrapKind::UrlTooLong,
+                pattern: path_pattern(url),
+            });
+        }
+
+        if limits.max_repeated_segments > 0
+            && max_segment_repeats(url) > limits.max_repeated_segments
+        {
+            return Some(Trap {
+                kind: TrapKind::RepeatedSegments,
+                pattern: path_pattern(url),
+            });
+        }
+
+        // A query variant already known or pending is accepted again without counting
+        let mut new_variant = None;
+        if limits.max_query_variants > 0 {
+            if let Some(query) = normalized_query(url) {
+                let key = path_key(url);
+                let known = self.state.query_variants.get(&key);
+                let pending = self.pending.query_variants.get(&key);
+                let seen = |variants: Option<&HashSet<String>>| variants.is_some_and(|v| v.contains(&query));
+                if !seen(known) && !seen(pending) {
+                    let count = known.map_or(0, HashSet::len) + pending.map_or(0, HashSet::len);
+                    if count >= limits.max_query_variants {
+                        return Some(Trap {
+                            kind: TrapKind::QueryVariants,
+                            pattern: format!("{}?*", key),
+                        });
+                    }
+                    new_variant = Some((key, query));
+                }
+            }
+        }
+
+        let pattern = path_pattern(url);
+        if limits.max_pages_per_pattern > 0 {
+            let count = self.state.pattern_counts.get(&pattern).copied().unwrap_or(0)
+                + self.pending.pattern_counts.get(&pattern).copied().unwrap_or(0);
+            if count >= limits.max_pages_per_pattern {
+                return Some(Trap {
+                    kind: TrapKind::PatternLimit,
+                    pattern,
+                });
+            }
+        }
+
+        if let Some((key, query)) = new_variant {
+            self.pending.query_variants.entry(key).or_default().insert(query);
+        }
+        if limits.max_pages_per_pattern > 0 {
+            *self.pending.pattern_counts.entry(pattern).or_insert(0) += 1;
+        }
+        None
+    }
+
+    /// Count the accepted URLs in the detector, once they were queued
+    pub fn record(mut self) {
+        let state = &mut *self.state;
+        for (key, variants) in self.pending.query_variants {
+            state.query_variants.entry(key).or_default().extend(variants);
+        }
+        for (pattern, count) in self.pending.pattern_counts {
+            *state.pattern_counts.entry(pattern).or_insert(0) += count;
+        }
+    }
+}
+
+/// Host and path of a URL, without the query string
+fn path_key(url: &Url) -> String {
+    format!("{}{}", url.host_str().unwrap_or(""), url.path())
+}
+
+/// Query string with parameters sorted, so ordering permutations count once
+fn normalized_query(url: &Url) -> Option<String> {
+    url.query()?;
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    pairs.sort();
+    let parts: Vec<String> = pairs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    Some(parts.join("&"))
+}
+
+/// Highest number of times a single path segment appears in the URL
+fn max_segment_repeats(url: &Url) -> usize {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for segment in url.path().split('/').filter(|s| !s.is_empty()) {
+        *counts.entry(segment).or_insert(0) += 1;
+    }
+    counts.values().copied().max().unwrap_or(0)
+}
+
+/// Generalize a URL into a path pattern, replacing digit runs with `{n}`
+///
+/// `/calendar/2024/05/12` and `/calendar/2031/11/02` share the pattern
+/// `host/calendar/{n}/{n}/{n}`.
+pub fn path_pattern(url: &Url) -> String {
+    let mut pattern = String::from(url.host_str().unwrap_or(""));
+    let mut in_digits = false;
+    for c in url.path().chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                pattern.push_str("{n}");
+                in_digits = true;
+            }
+        } else {
+            pattern.push(c);
+            in_digits = false;
+        }
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::CrawlOptions;
+
+    fn detector(max_url_length: usize, max_repeated_segments: usize, max_query_variants: usize, max_pages_per_pattern: usize) -> TrapDetector {
+        TrapDetector::new(TrapLimits {
+            max_url_length,
+            max_repeated_segments,
+            max_query_variants,
+            max_pages_per_pattern,
+        })
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let url = Url::parse("https://example.com/calendar/2024/05/12?view=day").unwrap();
+        assert_eq!(path_pattern(&url), "example.com/calendar/{n}/{n}/{n}");
+
+        let url = Url::parse("https://example.com/page-12/item").unwrap();
+        assert_eq!(path_pattern(&url), "example.com/page-{n}/item");
+    }
+
+    #[test]
+    fn test_url_length_and_repeated_segments() {
+        let traps = detector(40, 2, 0, 0);
+        let mut batch = traps.batch();
+
+        let long = Url::parse("https://example.com/a-very-long-path/that-keeps-going").unwrap();
+        assert_eq!(batch.check(&long).unwrap().kind, TrapKind::UrlTooLong);
+
+        let ok = Url::parse("https://example.com/a/b/a/b").unwrap();
+        assert!(batch.check(&ok).is_none());
+
+        let looping = Url::parse("https://example.com/a/b/a/b/a").unwrap();
+        assert_eq!(batch.check(&looping).unwrap().kind, TrapKind::RepeatedSegments);
+    }
+
+    #[test]
+    fn test_default_limits() {
+        let options = CrawlOptions::default();
+        let traps = TrapDetector::new(TrapLimits {
+            max_url_length: options.max_url_length,
+            max_repeated_segments: options.max_repeated_segments,
+            max_query_variants: options.max_query_variants,
+            max_pages_per_pattern: options.max_pages_per_pattern,
+        });
+        let mut batch = traps.batch();
+
+        let looping = Url::parse("https://example.com/a/b/a/b/a/b").unwrap();
+        assert_eq!(batch.check(&looping).unwrap().kind, TrapKind::RepeatedSegments);
+        let ok = Url::parse("https://example.com/blog/2024/blog-post").unwrap();
+        assert!(batch.check(&ok).is_none());
+    }
+
+    #[test]
+    fn test_query_variants() {
+        let traps = detector(0, 0, 2, 0);
+
+        let mut batch = traps.batch();
+        for query in ["color=red&size=m", "color=blue"] {
+            let url = Url::parse(&format!("https://example.com/shop?{}", query)).unwrap();
+            assert!(batch.check(&url).is_none());
+        }
+        batch.record();
+
+        // Permuted parameters normalize to an already seen variant
+        let mut batch = traps.batch();
+        let permuted = Url::parse("https://example.com/shop?size=m&color=red").unwrap();
+        assert!(batch.check(&permuted).is_none());
+
+        let third = Url::parse("https://example.com/shop?color=green").unwrap();
+        let trap = batch.check(&third).unwrap();
+        assert_eq!(trap.kind, TrapKind::QueryVariants);
+        assert_eq!(trap.pattern, "example.com/shop?*");
+    }
+
+    #[test]
+    fn test_pattern_limit() {
+        let traps = detector(0, 0, 0, 3);
+
+        // The batch counts the URLs it accepted
+        let mut batch = traps.batch();
+        for day in 1..=3 {
+            let url = Url::parse(&format!("https://example.com/calendar/2024/01/{:02}", day)).unwrap();
+            assert!(batch.check(&url).is_none());
+        }
+
+        let url = Url::parse("https://example.com/calendar/2024/01/04").unwrap();
+        assert_eq!(batch.check(&url).unwrap().kind, TrapKind::PatternLimit);
+
+        let other = Url::parse("https://example.com/contact").unwrap();
+        assert!(batch.check(&other).is_none());
+        batch.record();
+
+        assert_eq!(traps.batch().check(&url).unwrap().kind, TrapKind::PatternLimit);
+    }
+
+    #[test]
+    fn test_concurrent_batches() {
+        let traps = detector(0, 0, 3, 0);
+
+        // Workers checking at the same time can't accept more variants than the limit
+        let accepted: usize = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|worker| {
+                    let traps = &traps;
+                    scope.spawn(move || {
+                        let url = Url::parse(&format!("https://example.com/shop?page={}", worker)).unwrap();
+                        let mut batch = traps.batch();
+                        let accepted = batch.check(&url).is_none();
+                        batch.record();
+                        accepted as usize
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).sum()
+        });
+        assert_eq!(accepted, 3);
+    }
+
+    #[test]
+    fn test_unrecorded_batch() {
+        let traps = detector(0, 0, 1, 1);
+        let url = Url::parse("https://example.com/shop?color=red").unwrap();
+
+        // A batch that failed to queue its URLs doesn't count them
+        let mut batch = traps.batch();
+        assert!(batch.check(&url).is_none());
+        drop(batch);
+
+        let other = Url::parse("https://example.com/shop?color=blue").unwrap();
+        assert!(traps.batch().check(&other).is_none());
+    }
+}
@@ -0,0 +1,421 @@
+use chrono::{SecondsFormat, Utc};
+use data_encoding::BASE32;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Version};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Response headers describing the transfer, which no longer match the decoded body
+const TRANSFER_HEADERS: &[&str] = &["content-encoding", "content-length", "transfer-encoding"];
+
+/// A fetched HTTP request and response, as archived in WARC records
+pub struct HttpExchange {
+    pub url: String,
+    pub request_headers: HeaderMap,
+    pub status: StatusCode,
+    pub version: Version,
+    pub response_headers: HeaderMap,
+    pub remote_addr: Option<SocketAddr>,
+    pub body: Vec<u8>,
+}
+
+/// Writes request/response records to gzipped WARC 1.1 files, rotated by size
+///
+/// Each record is its own gzip member so files can be read record by record.
+/// Bodies are archived decoded: transfer headers are dropped and `Content-Length`
+/// is rewritten to match.
+pub struct WarcWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_size: u64,
+    current: Mutex<Option<WarcFile>>,
+}
+
+/// The file currently being written
+struct WarcFile {
+    file: File,
+    size: u64,
+    warcinfo_id: String,
+}
+
+impl WarcWriter {
+    /// Create a writer for `dir`, files are named `<prefix>-<timestamp>-<n>.warc.gz`
+    pub fn new(dir: &str, prefix: &str, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(WarcWriter {
+            dir: PathBuf::from(dir),
+            prefix: prefix.to_string(),
+            max_size,
+            current: Mutex::new(None),
+        })
+    }
+
+    /// Archive an exchange as a response record and its concurrent request record
+    pub fn write_exchange(&self, exchange: &HttpExchange) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap();
+
+        // Rotate once the current file reached its maximum size
+        if current.as_ref().is_none_or(|file| self.max_size > 0 && file.size >= self.max_size) {
+            *current = Some(self.open_file()?);
+        }
+        let file = current.as_mut().unwrap();
+
+        let date = warc_date();
+        let response_id = record_id();
+        let response = response_block(exchange);
+        let payload_start = response.len() - exchange.body.len();
+
+        let mut fields = vec![
+            ("WARC-Type", "response".to_string()),
+            ("WARC-Record-ID", response_id.clone()),
+            ("WARC-Warcinfo-ID", file.warcinfo_id.clone()),
+            ("WARC-Date", date.clone()),
+            ("WARC-Target-URI", exchange.url.clone()),
+        ];
+        if let Some(addr) = exchange.remote_addr {
+            fields.push(("WARC-IP-Address", addr.ip().to_string()));
+        }
+        fields.push(("WARC-Payload-Digest", digest(&response[payload_start..])));
+        fields.push(("Content-Type", "application/http; msgtype=response".to_string()));
+        let mut data = gzip_record(&fields, &response)?;
+
+        let request = request_block(exchange);
+        let fields = vec![
+            ("WARC-Type", "request".to_string()),
+            ("WARC-Record-ID", record_id()),
+            ("WARC-Warcinfo-ID", file.warcinfo_id.clone()),
+            ("WARC-Date", date),
+            ("WARC-Target-URI", exchange.url.clone()),
+            ("WARC-Concurrent-To", response_id),
+            ("Content-Type", "application/http; msgtype=request".to_string()),
+        ];
+        data.extend(gzip_record(&fields, &request)?);
+
+        file.file.write_all(&data)?;
+        file.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Start a new file with its `warcinfo` record
+    fn open_file(&self) -> io::Result<WarcFile> {
+        let timestamp = Utc::now().format("%Y%m%d%H%M%S");
+        let mut index = 0;
+        let path = loop {
+            let path = self.dir.join(format!("{}-{}-{:05}.warc.gz", self.prefix, timestamp, index));
+            if !path.exists() {
+                break path;
+            }
+            index += 1;
+        };
+
+        let mut file = File::create(&path)?;
+        let warcinfo_id = record_id();
+        let info = format!(
+            "software: couscous-crawler/{}\r\nformat: WARC File Format 1.1\r\nconformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let fields = vec![
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", warcinfo_id.clone()),
+            ("WARC-Date", warc_date()),
+            ("WARC-Filename", file_name(&path)),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ];
+        let data = gzip_record(&fields, info.as_bytes())?;
+        file.write_all(&data)?;
+
+        Ok(WarcFile { file, size: data.len() as u64, warcinfo_id })
+    }
+}
+
+/// A record read back from a WARC file
+pub struct WarcRecord {
+    pub warc_type: String,
+    pub target_uri: Option<String>,
+    pub block: Vec<u8>,
+}
+
+/// Reads records from a WARC file, gzipped or not
+pub struct WarcReader {
+    reader: Box<dyn BufRead>,
+}
+
+impl WarcReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let reader: Box<dyn BufRead> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        Ok(WarcReader { reader })
+    }
+
+    /// Read the next record, `None` at the end of the file
+    pub fn next_record(&mut self) -> io::Result<Option<WarcRecord>> {
+        // Skip the blank lines ending the previous record
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        if !line.starts_with("WARC/") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "missing WARC version line"));
+        }
+
+        let mut warc_type = String::new();
+        let mut target_uri = None;
+        let mut length = None;
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "warc-type" => warc_type = value.to_string(),
+                    "warc-target-uri" => target_uri = Some(value.to_string()),
+                    "content-length" => length = value.parse::<usize>().ok(),
+                    _ => {}
+                }
+            }
+        }
+
+        let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+        let mut block = vec![0; length];
+        self.reader.read_exact(&mut block)?;
+        Ok(Some(WarcRecord { warc_type, target_uri, block }))
+    }
+}
+
+/// WARC files of a directory in name order, or the path itself for a file
+pub fn warc_files(path: &str) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = file_name(path);
+            name.ends_with(".warc") || name.ends_with(".warc.gz")
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Split an archived HTTP response into its headers and body
+pub fn parse_response(block: &[u8]) -> Option<(HeaderMap, &[u8])> {
+    let end = block.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&block[..end]).ok()?;
+
+    let mut headers = HeaderMap::new();
+    for line in head.split("\r\n").skip(1) {
+        if let Some((name, value)) = line.split_once(':') {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.trim().as_bytes()),
+                HeaderValue::from_str(value.trim()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+    }
+    Some((headers, &block[end + 4..]))
+}
+
+/// Serialize and gzip a record from its header fields and content block
+fn gzip_record(fields: &[(&str, String)], block: &[u8]) -> io::Result<Vec<u8>> {
+    let mut record = Vec::with_capacity(block.len() + 512);
+    record.extend_from_slice(b"WARC/1.1\r\n");
+    for (name, value) in fields {
+        record.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    record.extend_from_slice(format!("WARC-Block-Digest: {}\r\n", digest(block)).as_bytes());
+    record.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&record)?;
+    encoder.finish()
+}
+
+/// HTTP response message: status line, headers and decoded body
+fn response_block(exchange: &HttpExchange) -> Vec<u8> {
+    let mut block = format!(
+        "{:?} {} {}\r\n",
+        exchange.version,
+        exchange.status.as_u16(),
+        exchange.status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in &exchange.response_headers {
+        if !TRANSFER_HEADERS.contains(&name.as_str()) {
+            push_header(&mut block, name.as_str(), value.as_bytes());
+        }
+    }
+    push_header(&mut block, "content-length", exchange.body.len().to_string().as_bytes());
+    block.extend_from_slice(b"\r\n");
+    block.extend_from_slice(&exchange.body);
+    block
+}
+
+/// HTTP request message: request line and headers
+fn request_block(exchange: &HttpExchange) -> Vec<u8> {
+    let (target, host) = match url::Url::parse(&exchange.url) {
+        Ok(url) => {
+            let mut target = url.path().to_string();
+            if let Some(query) = url.query() {
+                target.push('?');
+                target.push_str(query);
+            }
+            (target, url.host_str().unwrap_or("").to_string())
+        }
+        Err(_) => (exchange.url.clone(), String::new()),
+    };
+
+    let mut block = format!("GET {} {:?}\r\n", target, exchange.version).into_bytes();
+    push_header(&mut block, "host", host.as_bytes());
+    for (name, value) in &exchange.request_headers {
+        push_header(&mut block, name.as_str(), value.as_bytes());
+    }
+    block.extend_from_slice(b"\r\n");
+    block
+}
+
+fn push_header(block: &mut Vec<u8>, name: &str, value: &[u8]) {
+    block.extend_from_slice(name.as_bytes());
+    block.extend_from_slice(b": ");
+    block.extend_from_slice(value);
+    block.extend_from_slice(b"\r\n");
+}
+
+/// `sha1:` digest in base32, as used by WARC digests
+fn digest(data: &[u8]) -> String {
+    format!("sha1:{}", BASE32.encode(&sha1_smol::Sha1::from(data).digest().bytes()))
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+fn warc_date() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(body: &str) -> HttpExchange {
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert("accept", HeaderValue::from_static("text/html"));
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert("content-type", HeaderValue::from_static("text/html"));
+        response_headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+
+        HttpExchange {
+            url: "https://example.com/contact?lang=fr".to_string(),
+            request_headers,
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            response_headers,
+            remote_addr: None,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("couscous-warc-{}", Uuid::new_v4()))
+    }
+
+    fn read_files(dir: &Path) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| {
+                let mut text = String::new();
+                MultiGzDecoder::new(File::open(path).unwrap()).read_to_string(&mut text).unwrap();
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_exchange() {
+        let dir = temp_dir();
+        let writer = WarcWriter::new(dir.to_str().unwrap(), "test", 0).unwrap();
+        writer.write_exchange(&exchange("<p>hello</p>")).unwrap();
+
+        let files = read_files(&dir);
+        assert_eq!(files.len(), 1);
+        let text = &files[0];
+        assert!(text.starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n"));
+        assert!(text.contains("WARC-Type: response\r\n"));
+        assert!(text.contains("WARC-Type: request\r\n"));
+        assert!(text.contains("WARC-Target-URI: https://example.com/contact?lang=fr\r\n"));
+        assert!(text.contains("GET /contact?lang=fr HTTP/1.1\r\nhost: example.com\r\naccept: text/html\r\n"));
+        assert!(text.contains("HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: 12\r\n\r\n<p>hello</p>"));
+        assert!(!text.contains("content-encoding"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_back_records() {
+        let dir = temp_dir();
+        let writer = WarcWriter::new(dir.to_str().unwrap(), "test", 0).unwrap();
+        writer.write_exchange(&exchange("<p>hello</p>")).unwrap();
+
+        let files = warc_files(dir.to_str().unwrap()).unwrap();
+        assert_eq!(files.len(), 1);
+        let mut reader = WarcReader::open(&files[0]).unwrap();
+        let mut types = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            if record.warc_type == "response" {
+                assert_eq!(record.target_uri.as_deref(), Some("https://example.com/contact?lang=fr"));
+                let (headers, body) = parse_response(&record.block).unwrap();
+                assert_eq!(headers.get("content-type").unwrap(), "text/html");
+                assert_eq!(body, b"<p>hello</p>");
+            }
+            types.push(record.warc_type);
+        }
+        assert_eq!(types, vec!["warcinfo", "response", "request"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = temp_dir();
+        let writer = WarcWriter::new(dir.to_str().unwrap(), "test", 1).unwrap();
+        writer.write_exchange(&exchange("one")).unwrap();
+        writer.write_exchange(&exchange("two")).unwrap();
+
+        let files = read_files(&dir);
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|text| text.starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}